@@ -0,0 +1,118 @@
+use crate::registry::{cargo_home};
+
+use semver::{Version, VersionReq};
+use serde::{Deserialize};
+
+use std::{
+    collections::{HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+// local checkout of https://github.com/rustsec/advisory-db, only informational advisories used for now
+
+#[derive(Deserialize)]
+struct AdvisoryFile {
+    advisory: AdvisoryInfo,
+    #[serde(default)]
+    versions: AdvisoryVersions,
+}
+
+#[derive(Deserialize)]
+struct AdvisoryInfo {
+    id: String,
+    package: String,
+    informational: Option<String>,
+    withdrawn: Option<toml::Value>,
+}
+
+#[derive(Deserialize, Default)]
+struct AdvisoryVersions {
+    #[serde(default)]
+    patched: Vec<String>,
+    #[serde(default)]
+    unaffected: Vec<String>,
+}
+
+pub struct Advisory {
+    pub id: String,
+    pub informational: Option<String>,
+    patched: Vec<VersionReq>,
+    unaffected: Vec<VersionReq>,
+}
+
+impl Advisory {
+
+    pub fn affects(&self, version: &Version) -> bool {
+        !self.patched
+            .iter()
+            .chain(self.unaffected.iter())
+            .any(|requirement| requirement.matches(version))
+    }
+}
+
+pub struct AdvisoryDatabase {
+    advisories: HashMap<String, Vec<Advisory>>,
+}
+
+impl AdvisoryDatabase {
+
+    pub fn open(path: &Path) -> Option<AdvisoryDatabase> {
+
+        let crates_dir = path.join("crates");
+        if !crates_dir.is_dir() { return None; }
+
+        let mut advisories: HashMap<String, Vec<Advisory>> = HashMap::new();
+
+        for crate_dir in fs::read_dir(crates_dir).ok()?.filter_map(|entry| entry.ok()) {
+            let Ok(files) = fs::read_dir(crate_dir.path()) else { continue };
+            for file in files.filter_map(|entry| entry.ok()) {
+                if file.path().extension().is_none_or(|extension| extension != "md") { continue; }
+                let Ok(content) = fs::read_to_string(file.path()) else { continue };
+                if let Some(advisory_file) = parse_front_matter(&content) {
+                    if advisory_file.advisory.withdrawn.is_some() { continue; }
+                    advisories
+                        .entry(advisory_file.advisory.package.clone())
+                        .or_default()
+                        .push(Advisory {
+                            id: advisory_file.advisory.id,
+                            informational: advisory_file.advisory.informational,
+                            patched: parse_requirements(&advisory_file.versions.patched),
+                            unaffected: parse_requirements(&advisory_file.versions.unaffected),
+                        });
+                }
+            }
+        }
+
+        Some(AdvisoryDatabase { advisories })
+    }
+
+    pub fn default_path() -> Option<PathBuf> {
+        cargo_home().map(|home| home.join("advisory-db"))
+    }
+
+    pub fn unmaintained(&self, crate_name: &str, version: &Version) -> Vec<&Advisory> {
+        match self.advisories.get(crate_name) {
+            Some(advisories) => advisories
+                                    .iter()
+                                    .filter(|advisory| advisory.informational.as_deref() == Some("unmaintained"))
+                                    .filter(|advisory| advisory.affects(version))
+                                    .collect(),
+            None => vec![]
+        }
+    }
+}
+
+// advisories are markdown with the actual data in a ```toml block at the top
+fn parse_front_matter(content: &str) -> Option<AdvisoryFile> {
+    let toml_start = content.strip_prefix("```toml")?;
+    let toml_end = toml_start.find("\n```")?;
+    toml::from_str(&toml_start[..toml_end]).ok()
+}
+
+fn parse_requirements(requirements: &[String]) -> Vec<VersionReq> {
+    requirements
+        .iter()
+        .filter_map(|requirement| VersionReq::parse(requirement).ok())
+        .collect()
+}
@@ -1,100 +1,317 @@
-use cargo_metadata::{DependencyKind, Metadata, Node, Package};
+use crate::{
+    component::{Property},
+    dwarf::{DwarfSizes},
+    error::{Error},
+    invocation::{CargoInvocation},
+};
+
+use cargo_metadata::{DependencyKind, Metadata, Node, Package, TargetKind};
 use serde::{Serialize, Deserialize};
-use serde_json;
 
 use std::{
     collections::{HashMap, HashSet}, 
-    fs::File, 
-    io::{BufRead, BufReader}, 
+    ops::{AddAssign},
     path::Path, 
-    process::Command
 };
 
 #[derive(Deserialize, Serialize)]
 pub struct BloatOutput {
+    #[serde(rename = "text-section-size")]
+    text_section_size: Option<u64>,
     crates: Vec<BloatCrate>
 }
 
 #[derive(Deserialize, Serialize)]
 struct BloatCrate {
     #[serde(rename = "name")]
-    crate_name: String
+    crate_name: String,
+    #[serde(default)]
+    size: u64,
 }
 
-// TODO: 
-    // change to function output
-    // capture crates by that
-    // some form of list for stuff that gets recognized as unknown
 impl BloatOutput {
     
-    pub fn generate(project_path: &Path) -> BloatData {
+    pub fn generate(project_path: &Path, invocation: &CargoInvocation) -> Result<BloatData, Error> {
+
+        let mut command = invocation.command(project_path, "bloat", &["--crates", "-n", "100000", "--message-format", "json"]);
+        let command_string = format!("{:?}", command);
 
-        let file = match File::open(format!("{}build/build-local.ninja", project_path.display())) {
-            Ok(file) => file,
-            Err(e) => panic!("Could not open build-local.ninja: {}", e)        
+        let command_output = match command.output() {
+            Ok(output) => output,
+            Err(e) => return Err(Error::CargoBloat { command: command_string, status: None, stderr: e.to_string() })
         };
 
-        let reader = BufReader::new(file);
-        let mut lines: Vec<String> = vec![];
-        for line in reader.lines() {
-            match line {
-                Ok(content) => lines.push(content),
-                Err(_) => panic!("Failed to read build-local.ninja")
-            };
+        let stderr = String::from_utf8_lossy(&command_output.stderr).into_owned();
+        if !command_output.status.success() {
+            return Err(Error::CargoBloat { command: command_string, status: command_output.status.code(), stderr });
         }
 
-        let command_split: Vec<&str> = lines[3].split(" cargo ").collect();
-
-        let (command_left, command_middle, command_right): (&str, &str, &str) = (
-            &command_split[0][12..],
-            "cargo bloat --crates -n 100000 --message-format json",
-            &command_split[1].split(" &&").collect::<Vec<&str>>()[0]
-        );
-
-        let command_output = Command::new("sh")
-                .current_dir(project_path)
-                .arg("-c")
-                .arg(format!("{} {}{}", command_left, command_middle, command_right))
-                .output()
-                .expect("Something failed with cargo bloat")
-                .stdout;
-
         let cargo_bloat_output: BloatOutput = match serde_json::from_slice(
-            &command_output
+            &command_output.stdout
         ) {
             Ok(content) => content,
-            Err(e) => panic!("Could not deserialize cargo bloat data: {}", e)
+            Err(e) => return Err(Error::CargoBloatOutput { command: command_string, source: e, stderr })
         };
 
-        let mut bloat_set: HashSet<String> = HashSet::new();
+        let mut crate_sizes: HashMap<String, u64> = HashMap::new();
 
         for bloat_crate in cargo_bloat_output.crates.iter() {
-            bloat_set.insert(bloat_crate.crate_name.clone());
+            *crate_sizes.entry(bloat_crate.crate_name.clone()).or_default() += bloat_crate.size;
         }
 
-        BloatData { set: bloat_set }
+        let total_size = cargo_bloat_output.text_section_size.unwrap_or_else(|| crate_sizes.values().sum());
+        Ok(BloatData::from_crate_sizes(crate_sizes, total_size))
+
+    }
+}
+
+const FOOTPRINT_PROPERTIES: [&str; 6] = [
+    "arielosbom:flash_bytes",
+    "arielosbom:ram_bytes",
+    "arielosbom:text_bytes",
+    "arielosbom:rodata_bytes",
+    "arielosbom:data_bytes",
+    "arielosbom:bss_bytes",
+];
+
+// bytes by kind of section; flash holds code, read-only data and the initial values of .data, RAM .data and .bss
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Footprint {
+    pub text: u64,
+    pub rodata: u64,
+    pub data: u64,
+    pub bss: u64,
+}
+
+impl Footprint {
+
+    pub fn flash(&self) -> u64 {
+        self.text + self.rodata + self.data
+    }
+
+    pub fn ram(&self) -> u64 {
+        self.data + self.bss
+    }
+
+    // RAM is unknown if only .text is, and cargo bloat's .text is just the bytes of code debug info gives otherwise
+    pub fn properties(&self, text_only: bool) -> Vec<Property> {
+        if text_only {
+            return vec![Property::new("arielosbom:code_bytes", self.text)];
+        }
+        FOOTPRINT_PROPERTIES
+            .iter()
+            .zip([self.flash(), self.ram(), self.text, self.rodata, self.data, self.bss])
+            .map(|(name, bytes)| Property::new(name, bytes))
+            .collect()
+    }
+
+    pub fn is_property(name: &str) -> bool {
+        FOOTPRINT_PROPERTIES.contains(&name)
+    }
+}
 
+impl AddAssign for Footprint {
+    fn add_assign(&mut self, other: Footprint) {
+        self.text += other.text;
+        self.rodata += other.rodata;
+        self.data += other.data;
+        self.bss += other.bss;
     }
 }
 
 pub struct BloatData{
-    set: HashSet<String>
+    // crate name -> bytes
+    crates: HashMap<String, u64>,
+    // crate name -> bytes per section kind, only .text from cargo bloat
+    footprints: HashMap<String, Footprint>,
+    // allocated sections of the whole image
+    image_footprint: Footprint,
+    // cargo bloat only knows the text section
+    text_only: bool,
+    // result of match_packages: package id -> footprint of the crates only it can be
+    package_footprints: HashMap<String, Footprint>,
+    // bytes the crate sizes are a part of (code + data of all symbols, or cargo bloat's text section)
+    total_size: u64,
+    // only from debug info: package id -> bytes of code
+    package_sizes: HashMap<String, u64>,
+    // package ids known to be linked exactly (debug info, rlib symbols), not just by crate name
+    exact_packages: HashSet<String>,
+    // result of match_packages: package ids of everything linked
+    linked_packages: HashSet<String>,
+    // crate names in the firmware no package matches, with their bytes
+    unmatched_crates: Vec<(String, u64)>,
 }
 
 impl BloatData {
 
+    pub fn from_crate_sizes(crates: HashMap<String, u64>, total_size: u64) -> BloatData {
+        BloatData {
+            footprints: crates.iter().map(|(name, size)| (name.clone(), Footprint { text: *size, ..Footprint::default() })).collect(),
+            image_footprint: Footprint { text: total_size, ..Footprint::default() },
+            text_only: true,
+            package_footprints: HashMap::new(),
+            crates,
+            total_size,
+            package_sizes: HashMap::new(),
+            exact_packages: HashSet::new(),
+            linked_packages: HashSet::new(),
+            unmatched_crates: vec![],
+        }
+    }
+
+    pub fn add_dwarf_sizes(&mut self, sizes: DwarfSizes) {
+        for (name, size) in sizes.toolchain_crates {
+            self.crates.entry(name).or_insert(size);
+        }
+        self.exact_packages.extend(sizes.packages.keys().cloned());
+        self.package_sizes = sizes.packages;
+    }
+
+    // from the firmware's symbol and section tables
+    pub fn set_footprints(&mut self, footprints: HashMap<String, Footprint>, image_footprint: Footprint) {
+        self.footprints = footprints;
+        self.image_footprint = image_footprint;
+        self.text_only = false;
+    }
+
+    pub fn add_exact_packages(&mut self, ids: impl IntoIterator<Item = String>) {
+        self.exact_packages.extend(ids);
+    }
+
+    // linked without Rust code of their own in the firmware (-sys crates of native libraries)
+    pub fn add_linked_packages(&mut self, ids: impl IntoIterator<Item = String>) {
+        self.linked_packages.extend(ids);
+    }
+
+    pub fn is_exact(&self, package: &Package) -> bool {
+        self.exact_packages.contains(&package.id.repr)
+    }
+
+    // crate names -> packages via their lib/bin target names;
+    // for crates that are in the graph several times only versions known to be linked stay
+    pub fn match_packages(&mut self, metadata: &Metadata) {
+
+        let mut resolved_crates: HashSet<String> = HashSet::new();
+        for (name, packages) in ambiguous_crates(metadata) {
+            let linked: Vec<String> = packages
+                                        .iter()
+                                        .filter(|package| self.is_exact(package))
+                                        .map(|package| package.version.to_string())
+                                        .collect();
+            let all: Vec<String> = packages.iter().map(|package| package.version.to_string()).collect();
+            match linked.is_empty() {
+                true if self.contains(&name) => println!("Cannot tell which version of {} is linked ({}), keeping all", name, all.join(", ")),
+                true => {},
+                false => {
+                    println!("Linked version(s) of {}: {} (of {})", name, linked.join(", "), all.join(", "));
+                    resolved_crates.insert(name);
+                }
+            }
+        }
+
+        let package_names = package_crate_names(metadata);
+        self.linked_packages = metadata.packages
+                                .iter()
+                                .filter(|package| self.is_exact(package)
+                                                    || package_names[&package.id.repr]
+                                                        .iter()
+                                                        .any(|name| self.contains(name) && !resolved_crates.contains(name)))
+                                .map(|package| package.id.repr.clone())
+                                .collect();
+
+        // a crate name several linked packages have (versions nothing told apart) isn't attributed
+        let mut crate_packages: HashMap<&String, Vec<&String>> = HashMap::new();
+        for (id, names) in package_names.iter().filter(|(id, _)| self.linked_packages.contains(*id)) {
+            for name in names { crate_packages.entry(name).or_default().push(id); }
+        }
+        self.package_footprints = HashMap::new();
+        for (name, ids) in crate_packages {
+            if let ([id], Some(footprint)) = (ids.as_slice(), self.footprints.get(name)) {
+                *self.package_footprints.entry((*id).clone()).or_default() += *footprint;
+            }
+        }
+
+        // [Unknown] (C, assembly, ...), the toolchain's crates and everything else cargo metadata doesn't know
+        let known_names: HashSet<&String> = package_names.values().flatten().collect();
+        self.unmatched_crates = self.crates
+                                    .iter()
+                                    .filter(|(name, _)| !known_names.contains(name))
+                                    .map(|(name, size)| (name.clone(), *size))
+                                    .collect();
+        self.unmatched_crates.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    }
+
+    pub fn print_unmatched(&self) {
+        if self.unmatched_crates.is_empty() { return; }
+        println!("Not attributable to a component: {} of {} bytes", self.unmatched_size(), self.total_size);
+        for (name, size) in self.unmatched_crates.iter() { println!("    {:<24} {}", name, size); }
+    }
+
+    pub fn unmatched_crates(&self) -> &[(String, u64)] {
+        &self.unmatched_crates
+    }
+
+    // parts that turned out to be components after all (e.g. the standard library)
+    pub fn remove_unmatched(&mut self, names: &[String]) {
+        self.unmatched_crates.retain(|(name, _)| !names.contains(name));
+    }
+
+    pub fn contains_crate(&self, name: &str) -> bool {
+        self.crates.contains_key(name)
+    }
+
+    pub fn unmatched_size(&self) -> u64 {
+        self.unmatched_crates.iter().map(|(_, size)| size).sum()
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.total_size
+    }
+
+    pub fn package_size(&self, package: &Package) -> Option<u64> {
+        self.package_sizes.get(&package.id.repr).copied()
+    }
+
+    pub fn package_footprint(&self, package: &Package) -> Option<Footprint> {
+        self.package_footprints.get(&package.id.repr).copied()
+    }
+
+    pub fn crate_footprint(&self, name: &str) -> Option<Footprint> {
+        self.footprints.get(name).copied()
+    }
+
+    // by component id: the packages (after match_packages) and the standard library crates (crate name -> id)
+    pub fn component_footprints(&self, std_ids: &HashMap<String, String>) -> HashMap<String, Footprint> {
+        let mut footprints = self.package_footprints.clone();
+        footprints.extend(std_ids.iter().filter_map(|(name, id)| Some((id.clone(), self.crate_footprint(name)?))));
+        footprints
+    }
+
+    pub fn image_footprint(&self) -> Footprint {
+        self.image_footprint
+    }
+
+    pub fn is_text_only(&self) -> bool {
+        self.text_only
+    }
+
     fn contains(&self, value: &String) -> bool {
-        self.set.contains(value)
+        self.crates.contains_key(value)
     }
 
-    pub fn filter_cargo_metadata(&self, mut metadata: Metadata) -> Metadata {
+    // only after match_packages
+    pub fn is_linked(&self, package: &Package) -> bool {
+        self.linked_packages.contains(&package.id.repr)
+    }
+
+    pub fn filter_cargo_metadata(&self, mut metadata: Metadata) -> Result<Metadata, Error> {
 
         let mut bloat_filter_set: HashSet<usize> = HashSet::new();
         let mut build_filter_set: HashSet<usize> = HashSet::new();
 
         // comment for self for now: everything that is in cargo bloat
         for (index, package) in metadata.packages.iter().enumerate() {
-            if self.contains(&package.name.replace("-", "_")) {
+            if self.is_linked(package) {
                 bloat_filter_set.insert(index);
             }
         }
@@ -108,36 +325,38 @@ impl BloatData {
                                                                             .map(|(index, package)| (&package.id.repr, index))
         );
 
-        let resolve_unwrap = metadata.resolve.as_mut().unwrap();
+        let resolve_unwrap = match metadata.resolve.as_mut() {
+            Some(resolve) => resolve,
+            None => return Err(Error::MetadataContent { reason: "no dependency resolve graph".into() })
+        };
+        if resolve_unwrap.nodes.len() != metadata.packages.len() {
+            return Err(Error::MetadataContent { reason: "number of resolve nodes and packages differ".into() });
+        }
 
+        let index_of = |pkg_id: &String| match pkg_id_index_map.get(pkg_id) {
+            Some(index) => Ok(*index),
+            None => Err(Error::MetadataContent { reason: format!("{} is in the resolve graph but not in the packages", pkg_id) })
+        };
 
         // initialize first layer of non-bloat deps
-        // this should probably be made more readable?......
-        let mut current_set: HashSet<usize> = resolve_unwrap.nodes
-                                                .iter()
-                                                .enumerate()
-                                                .filter(|(index, _)| bloat_filter_set.contains(index))
-                                                .map(
-                                                    |(_, node)|
-                                                    node.deps
-                                                        .iter()
-                                                        .filter(
-                                                            |dep| 
-                                                            dep.dep_kinds
-                                                                .iter()
-                                                                .any(|dep_kind_info|dep_kind_info.kind == DependencyKind::Build)
-                                                        )
-                                                        .map(|dep| pkg_id_index_map.get(&dep.pkg.repr).expect("Something went wrong retrieving the index of a package").clone())
-                                                )
-                                                .flatten()
-                                                .collect();
-        for index in &current_set { build_filter_set.insert(index.clone()); }
+        let mut current_set: HashSet<usize> = HashSet::new();
+        for (_, node) in resolve_unwrap.nodes
+                            .iter()
+                            .enumerate()
+                            .filter(|(index, _)| bloat_filter_set.contains(index)) {
+            for dep in node.deps
+                        .iter()
+                        .filter(|dep| dep.dep_kinds.iter().any(|dep_kind_info| dep_kind_info.kind == DependencyKind::Build)) {
+                current_set.insert(index_of(&dep.pkg.repr)?);
+            }
+        }
+        for index in &current_set { build_filter_set.insert(*index); }
 
         while !(current_set.is_empty()) {
             let mut next_set: HashSet<usize> = HashSet::new();
             for index in current_set {
                 for dep in resolve_unwrap.nodes[index].deps.iter() {
-                    let dep_index = pkg_id_index_map.get(&dep.pkg.repr).unwrap().clone();
+                    let dep_index = index_of(&dep.pkg.repr)?;
                     if build_filter_set.insert(dep_index) { next_set.insert(dep_index);}
                 }
             }
@@ -147,7 +366,7 @@ impl BloatData {
         // bools as extra set as output later for SBOM to declare packages only used relevant for build scripts?
         let combined_set: HashSet<usize> = bloat_filter_set
                                             .union(&build_filter_set)
-                                            .map(|index| index.clone())
+                                            .copied()
                                             .collect();
         let mut combined_array: Vec<usize> = combined_set.clone().into_iter().collect();
         combined_array.sort();
@@ -165,25 +384,57 @@ impl BloatData {
         metadata.packages = new_package_vec;
         resolve_unwrap.nodes = new_node_dep_vec;
 
-        return metadata;
+        Ok(metadata)
     }
 
 }
 
+// names the package's crates have in symbols/cargo bloat output, "[lib] name = ..." included
+pub fn crate_names(package: &Package) -> Vec<String> {
+    let mut names: Vec<String> = package.targets
+                                    .iter()
+                                    .filter(|target| !target.kind.iter().any(|kind| matches!(kind, TargetKind::CustomBuild | TargetKind::Test | TargetKind::Bench | TargetKind::Example)))
+                                    .map(|target| target.name.replace("-", "_"))
+                                    .collect();
+    if names.is_empty() { names.push(package.name.replace("-", "_")); }
+    names.dedup();
+    names
+}
+
+// package id -> crate names; renaming a dependency (package = "...") only changes the extern name, not the crate's
+fn package_crate_names(metadata: &Metadata) -> HashMap<String, Vec<String>> {
+    metadata.packages
+        .iter()
+        .map(|package| (package.id.repr.clone(), crate_names(package)))
+        .collect()
+}
+
+// lib crate name -> packages, for crates that are in the graph in several versions
+pub fn ambiguous_crates(metadata: &Metadata) -> HashMap<String, Vec<&Package>> {
+    let mut crates: HashMap<String, Vec<&Package>> = HashMap::new();
+    for package in metadata.packages.iter() {
+        for name in crate_names(package) {
+            crates.entry(name).or_default().push(package);
+        }
+    }
+    crates.retain(|_, packages| packages.len() > 1);
+    crates
+}
+
 fn create_filtered_metadata_node(node: &Node, pkg_id_index_map: &HashMap<&String, usize>, combined_set: &HashSet<usize>) -> Node {
 
     let mut new_node = node.clone();
 
     new_node.dependencies = new_node.dependencies
                                 .iter()
-                                .filter(|dependency| combined_set.contains(pkg_id_index_map.get(&dependency.repr).unwrap()))
-                                .map(|pkg_id| pkg_id.clone())
+                                .filter(|dependency| pkg_id_index_map.get(&dependency.repr).is_some_and(|index| combined_set.contains(index)))
+                                .cloned()
                                 .collect();
     new_node.deps = new_node.deps
                         .iter()
-                        .filter(|dep| combined_set.contains(pkg_id_index_map.get(&dep.pkg.repr).unwrap()))
-                        .map(|node_dep| node_dep.clone())
+                        .filter(|dep| pkg_id_index_map.get(&dep.pkg.repr).is_some_and(|index| combined_set.contains(index)))
+                        .cloned()
                         .collect();
 
-    return new_node;
+    new_node
 }
\ No newline at end of file
@@ -52,6 +52,64 @@ pub struct Args {
     )]
     pub project_lock_path: PathBuf,
 
+    // only needed if build-local.ninja contains more than one app/builder
+    #[arg(
+        id = "app",
+        value_name = "APP",
+        long = "app",
+        required = false
+    )]
+    pub app: Option<String>,
+
+    // several builders or "all" -> one SBOM per builder
+    #[arg(
+        id = "builders",
+        value_name = "BUILDER",
+        num_args = 1..,
+        long = "builder",
+        required = false
+    )]
+    pub builders: Vec<String>,
+
+    // for projects not built with laze: no build-local.ninja, cargo build arguments given directly
+    #[arg(
+        id = "plain_cargo",
+        value_name = "BOOL",
+        default_value = "false",
+        long = "plain-cargo",
+        required = false
+    )]
+    pub plain_cargo: bool,
+
+    // plain cargo mode: everything after "--", e.g. -- --target thumbv7em-none-eabihf --features foo --bin app --profile release
+    #[arg(
+        id = "cargo_args",
+        value_name = "CARGO_ARGS",
+        last = true,
+        required = false
+    )]
+    pub cargo_args: Vec<String>,
+
+    // plain cargo mode: environment for the cargo calls
+    #[arg(
+        id = "cargo_env",
+        value_name = "KEY=VALUE",
+        num_args = 1..,
+        long = "cargo-env",
+        required = false
+    )]
+    pub cargo_env: Vec<String>,
+
+    // additional product family SBOM over all builders: <FILE_NAME>-family.<FILE_EXTENSION>
+    #[arg(
+        id = "aggregate",
+        value_name = "BOOL",
+        default_value = "false",
+        long = "aggregate",
+        required = false
+    )]
+    pub aggregate: bool,
+
     #[arg(
         id = "BOM_formats",
         value_name = "BOM_FORMAT",
@@ -92,6 +150,72 @@ pub struct Args {
         required = false
     )]
     pub bloat_filter: bool,
+
+    // firmware to analyze instead of laze's build output/cargo's artifact (only with a single build)
+    #[arg(
+        id = "elf_path",
+        value_name = "PATH",
+        long = "elf",
+        required = false
+    )]
+    pub elf_path: Option<PathBuf>,
+
+    // linker map of the firmware (-Map) instead of the one in the link args/next to the ELF (only with a single build)
+    #[arg(
+        id = "map_path",
+        value_name = "PATH",
+        long = "map",
+        required = false
+    )]
+    pub map_path: Option<PathBuf>,
+
+    // skip the ELF analysis and always run cargo bloat (builds again)
+    #[arg(
+        id = "cargo_bloat",
+        value_name = "BOOL",
+        default_value = "false",
+        long = "cargo-bloat",
+        required = false
+    )]
+    pub cargo_bloat: bool,
+
+    // default: $CARGO_HOME/registry/index, skipped if not there
+    #[arg(
+        id = "registry_index_path",
+        value_name = "PATH",
+        long = "registry-index",
+        required = false
+    )]
+    pub registry_index_path: Option<PathBuf>,
+
+    // default: $CARGO_HOME/advisory-db (same as cargo audit), skipped if not there
+    #[arg(
+        id = "advisory_db_path",
+        value_name = "PATH",
+        long = "advisory-db",
+        required = false
+    )]
+    pub advisory_db_path: Option<PathBuf>,
+
+    // needs the registry index, only covers crates in the executable
+    #[arg(
+        id = "outdated_report",
+        value_name = "BOOL",
+        default_value = "false",
+        long = "outdated-report",
+        required = false
+    )]
+    pub outdated_report: bool,
+
+    // needs a linker map, flash/RAM per component
+    #[arg(
+        id = "size_report",
+        value_name = "BOOL",
+        default_value = "false",
+        long = "size-report",
+        required = false
+    )]
+    pub size_report: bool,
 }
 
 // impls for clap parsing
@@ -9,6 +9,19 @@ pub struct Dependency {
     pub build: bool
 }
 
+// name/value pairs for everything that doesn't have its own field (yet)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Property {
+    pub name: String,
+    pub value: String
+}
+
+impl Property {
+    pub fn new(name: &str, value: impl ToString) -> Property {
+        Property { name: name.into(), value: value.to_string() }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct Component {
 
@@ -16,6 +29,8 @@ pub struct Component {
     source: ComponentSource,
     pub id: String,
     pub version: Version,
+    #[serde(default)]
+    pub component_type: ComponentType,
     pub creators: Vec<String>, // TODO: enhance authors
     pub filename: Option<String>,
     pub licenses: Option<String>, // more specified later
@@ -31,6 +46,12 @@ pub struct Component {
 
     pub whatever_additional_temp: Vec<String>,
 
+    pub properties: Vec<Property>,
+
+    // component id of what it's part of/built by (e.g. the -sys crate for native libraries)
+    #[serde(default)]
+    pub parent: Option<String>,
+
     // dependencies to be simply stored as index references into the packages
     // whether it's in the executable or build related (or if everything used: completely out of scope?)
         // not used right now
@@ -47,6 +68,7 @@ impl Component {
             id: package.id.repr.clone(),
             name: package.name.to_string(),
             version: package.version.clone(),
+            component_type: ComponentType::Library,
             creators: package.authors.clone(),
             filename: None,
             licenses: package.license.clone(),
@@ -66,7 +88,238 @@ impl Component {
 
             whatever_additional_temp: vec![],
 
-            dependencies: dependencies,
+            properties: vec![],
+
+            parent: None,
+
+            dependencies,
+        }
+    }
+
+    // crates of the Rust standard library (sysroot), not part of the project's cargo metadata
+    pub fn create_toolchain_component(id: String, name: &str, version: Version, hash: Option<&Checksum>, uri_source_code: Option<String>, licenses: Option<String>, dependencies: Vec<Dependency>) -> Component {
+        Component {
+            source: ComponentSource::Toolchain,
+            id,
+            name: name.into(),
+            version,
+            component_type: ComponentType::Library,
+            creators: vec!["The Rust Project Developers".into()],
+            filename: None,
+            licenses,
+            identifiers: match hash {
+                Some(hash) => vec![hash.to_string()],
+                None => vec![],
+            },
+
+            executable_property: None,
+            archive_property: None,
+            structured_property: None,
+
+            uri_source_code,
+            hash_source_code: None,
+            uri_deployable_form: None,
+            url_security_text: None,
+
+            whatever_additional_temp: vec![],
+
+            properties: vec![],
+
+            parent: None,
+
+            dependencies,
+        }
+    }
+
+    // C/C++ libraries built or linked by a crate's build script, version unknown
+    pub fn create_native_component(id: String, name: &str, parent: String, filename: Option<String>, hash: Option<String>) -> Component {
+        Component {
+            source: ComponentSource::Other,
+            id,
+            name: name.into(),
+            version: Version::new(0, 0, 0),
+            component_type: ComponentType::Library,
+            creators: vec![],
+            filename,
+            licenses: None,
+            identifiers: hash.into_iter().collect(),
+
+            executable_property: None,
+            archive_property: None,
+            structured_property: None,
+
+            uri_source_code: None,
+            hash_source_code: None,
+            uri_deployable_form: None,
+            url_security_text: None,
+
+            whatever_additional_temp: vec![],
+
+            properties: vec![],
+
+            parent: Some(parent),
+
+            dependencies: vec![],
+        }
+    }
+
+    // C/C++ library whose sources a -sys crate ships, found by version headers/build files
+    pub fn create_vendored_component(id: String, name: &str, version: Version, parent: String, purl: String, licenses: Option<String>) -> Component {
+        Component {
+            source: ComponentSource::Other,
+            id,
+            name: name.into(),
+            version,
+            component_type: ComponentType::Library,
+            creators: vec![],
+            filename: None,
+            licenses,
+            identifiers: vec![purl],
+
+            executable_property: None,
+            archive_property: None,
+            structured_property: None,
+
+            uri_source_code: None,
+            hash_source_code: None,
+            uri_deployable_form: None,
+            url_security_text: None,
+
+            whatever_additional_temp: vec![],
+
+            properties: vec![],
+
+            parent: Some(parent),
+
+            dependencies: vec![],
+        }
+    }
+
+    // files from the build that shape the firmware without being code (linker scripts, ...)
+    pub fn create_file_component(id: String, name: &str, parent: Option<String>, hash: Option<String>) -> Component {
+        Component {
+            source: ComponentSource::Other,
+            id,
+            name: name.into(),
+            version: Version::new(0, 0, 0),
+            component_type: ComponentType::File,
+            creators: vec![],
+            filename: Some(name.into()),
+            licenses: None,
+            identifiers: hash.into_iter().collect(),
+
+            executable_property: None,
+            archive_property: None,
+            structured_property: None,
+
+            uri_source_code: None,
+            hash_source_code: None,
+            uri_deployable_form: None,
+            url_security_text: None,
+
+            whatever_additional_temp: vec![],
+
+            properties: vec![],
+
+            parent,
+
+            dependencies: vec![],
+        }
+    }
+
+    // the linked image of a package, root of the dependency tree
+    pub fn create_firmware_component(id: String, package: &Package, filename: String, identifiers: Vec<String>, dependencies: Vec<Dependency>) -> Component {
+        Component {
+            source: ComponentSource::Other,
+            id,
+            name: package.name.to_string(),
+            version: package.version.clone(),
+            component_type: ComponentType::Firmware,
+            creators: package.authors.clone(),
+            filename: Some(filename),
+            licenses: package.license.clone(),
+            identifiers,
+
+            executable_property: Some(true),
+            archive_property: None,
+            structured_property: None,
+
+            uri_source_code: package.repository.clone(),
+            hash_source_code: None,
+            uri_deployable_form: None,
+            url_security_text: None,
+
+            whatever_additional_temp: vec![],
+
+            properties: vec![],
+
+            parent: None,
+
+            dependencies,
+        }
+    }
+
+    // the hardware the firmware runs on, named after the chip
+    pub fn create_device_component(id: String, name: &str) -> Component {
+        Component {
+            source: ComponentSource::Other,
+            id,
+            name: name.into(),
+            version: Version::new(0, 0, 0),
+            component_type: ComponentType::Device,
+            creators: vec![],
+            filename: None,
+            licenses: None,
+            identifiers: vec![],
+
+            executable_property: None,
+            archive_property: None,
+            structured_property: None,
+
+            uri_source_code: None,
+            hash_source_code: None,
+            uri_deployable_form: None,
+            url_security_text: None,
+
+            whatever_additional_temp: vec![],
+
+            properties: vec![],
+
+            parent: None,
+
+            dependencies: vec![],
+        }
+    }
+
+    // a crate's build script, parent of what it generated
+    pub fn create_build_script_component(id: String, name: &str, version: Version, parent: String) -> Component {
+        Component {
+            source: ComponentSource::Other,
+            id,
+            name: name.into(),
+            version,
+            component_type: ComponentType::Application,
+            creators: vec![],
+            filename: Some("build.rs".into()),
+            licenses: None,
+            identifiers: vec![],
+
+            executable_property: Some(true),
+            archive_property: None,
+            structured_property: None,
+
+            uri_source_code: None,
+            hash_source_code: None,
+            uri_deployable_form: None,
+            url_security_text: None,
+
+            whatever_additional_temp: vec![],
+
+            properties: vec![],
+
+            parent: Some(parent),
+
+            dependencies: vec![],
         }
     }
 
@@ -77,9 +330,31 @@ impl Component {
 pub enum ComponentSource {
     CargoMetadata,
     CargoBloat, // 
+    Toolchain,
     Other
 }
 
+// CycloneDX-like component types, firmware/binary for prebuilt blobs nobody compiled from source in this build
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ComponentType {
+    #[default]
+    #[serde(rename = "library")]
+    Library,
+    #[serde(rename = "firmware/binary")]
+    Binary,
+    #[serde(rename = "file")]
+    File,
+    // build scripts
+    #[serde(rename = "application")]
+    Application,
+    // the linked image itself
+    #[serde(rename = "firmware")]
+    Firmware,
+    // the MCU the firmware was built for
+    #[serde(rename = "device")]
+    Device,
+}
+
 //#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 //enum License {
 //
@@ -0,0 +1,236 @@
+use cargo_metadata::{Metadata};
+use gimli::{AttributeValue, DebuggingInformationEntry, Dwarf, EndianSlice, RunTimeEndian, Unit};
+use object::{Object, ObjectSection, SectionKind};
+
+use std::{
+    borrow::{Cow},
+    collections::{BTreeSet, HashMap},
+    path::{Component, Path, PathBuf},
+};
+
+type Reader<'data> = EndianSlice<'data, RunTimeEndian>;
+
+// bytes of code per package, attributed via the source file of the (inlined) function
+// the code comes from, so inlined and monomorphized code counts for the crate it's written in
+#[derive(Debug, Default)]
+pub struct DwarfSizes {
+    // package id -> bytes
+    pub packages: HashMap<String, u64>,
+    // crates that aren't cargo packages, i.e. std/core/alloc from the toolchain
+    pub toolchain_crates: HashMap<String, u64>,
+    pub unattributed: u64,
+}
+
+// code range of one function or inlined call, the deepest one covering an address owns it
+struct Span {
+    begin: u64,
+    end: u64,
+    depth: isize,
+    file: usize,
+}
+
+pub struct DwarfAnalyzer;
+
+impl DwarfAnalyzer {
+
+    // None if the firmware has no debug info
+    pub fn analyze(file: &object::File, metadata: &Metadata) -> Result<Option<DwarfSizes>, String> {
+
+        if file.section_by_name(".debug_info").is_none() {
+            return Ok(None);
+        }
+
+        let endian = match file.is_little_endian() {
+            true => RunTimeEndian::Little,
+            false => RunTimeEndian::Big,
+        };
+        let sections = gimli::DwarfSections::load(|id| -> Result<Cow<[u8]>, String> {
+            match file.section_by_name(id.name()) {
+                Some(section) => section.uncompressed_data().map_err(|e| e.to_string()),
+                None => Ok(Cow::Borrowed(&[])),
+            }
+        })?;
+        let dwarf = sections.borrow(|section| EndianSlice::new(section, endian));
+
+        // everything else (debug info of sections the linker dropped, tombstones) isn't in the image
+        let code_ranges: Vec<(u64, u64)> = file.sections()
+                                                .filter(|section| section.kind() == SectionKind::Text)
+                                                .map(|section| (section.address(), section.address() + section.size()))
+                                                .collect();
+
+        let mut units: Vec<Unit<Reader>> = vec![];
+        let mut headers = dwarf.units();
+        while let Some(header) = headers.next().map_err(|e| e.to_string())? {
+            units.push(dwarf.unit(header).map_err(|e| e.to_string())?);
+        }
+
+        let mut files: Vec<PathBuf> = vec![];
+        let mut file_indices: HashMap<PathBuf, usize> = HashMap::new();
+        let mut spans: Vec<Span> = vec![];
+
+        for unit in units.iter() {
+            let mut entries = unit.entries();
+            while let Some(entry) = entries.next_dfs().map_err(|e| e.to_string())? {
+                if entry.tag() != gimli::DW_TAG_subprogram && entry.tag() != gimli::DW_TAG_inlined_subroutine {
+                    continue;
+                }
+
+                let mut ranges = dwarf.die_ranges(unit, entry).map_err(|e| e.to_string())?;
+                let mut entry_ranges: Vec<(u64, u64)> = vec![];
+                while let Some(range) = ranges.next().map_err(|e| e.to_string())? {
+                    if range.begin < range.end
+                        && code_ranges.iter().any(|(begin, end)| *begin <= range.begin && range.end <= *end) {
+                        entry_ranges.push((range.begin, range.end));
+                    }
+                }
+                if entry_ranges.is_empty() { continue; }
+
+                let Some(path) = declaration_file(&dwarf, &units, unit, entry) else { continue };
+                let file = *file_indices.entry(path.clone()).or_insert_with(|| {
+                    files.push(path);
+                    files.len() - 1
+                });
+
+                for (begin, end) in entry_ranges {
+                    spans.push(Span { begin, end, depth: entry.depth(), file });
+                }
+            }
+        }
+
+        let file_sizes = attribute_spans(&spans, files.len());
+
+        let package_roots = package_roots(metadata);
+        let mut sizes = DwarfSizes::default();
+        for (path, size) in files.iter().zip(file_sizes).filter(|(_, size)| *size > 0) {
+            match source_owner(path, metadata, &package_roots) {
+                SourceOwner::Package(id) => *sizes.packages.entry(id).or_default() += size,
+                SourceOwner::Toolchain(name) => *sizes.toolchain_crates.entry(name).or_default() += size,
+                SourceOwner::Unknown => sizes.unattributed += size,
+            }
+        }
+
+        Ok(Some(sizes))
+    }
+}
+
+// sweep over all span borders, every byte goes to the innermost span (inlined call over its caller)
+fn attribute_spans(spans: &[Span], file_count: usize) -> Vec<u64> {
+    let mut borders: Vec<(u64, bool, usize)> = Vec::with_capacity(spans.len() * 2);
+    for (index, span) in spans.iter().enumerate() {
+        borders.push((span.begin, true, index));
+        borders.push((span.end, false, index));
+    }
+    borders.sort();
+
+    let mut file_sizes: Vec<u64> = vec![0; file_count];
+    let mut active: BTreeSet<(isize, usize)> = BTreeSet::new();
+    let mut previous: u64 = 0;
+
+    for (address, is_begin, index) in borders {
+        if let Some((_, owner)) = active.last() {
+            file_sizes[spans[*owner].file] += address - previous;
+        }
+        previous = address;
+        match is_begin {
+            true => active.insert((spans[index].depth, index)),
+            false => active.remove(&(spans[index].depth, index)),
+        };
+    }
+
+    file_sizes
+}
+
+// DW_AT_decl_file of the entry or of where it's from (concrete out-of-line/inlined instance -> abstract origin)
+fn declaration_file<'data>(dwarf: &Dwarf<Reader<'data>>, units: &[Unit<Reader<'data>>], unit: &Unit<Reader<'data>>, entry: &DebuggingInformationEntry<Reader<'data>>) -> Option<PathBuf> {
+    let mut unit = unit;
+    let mut entry = entry.clone();
+    // origins can chain (inlined instance -> concrete instance -> declaration)
+    for _ in 0..8 {
+        if let Some(AttributeValue::FileIndex(index)) = entry.attr_value(gimli::DW_AT_decl_file) {
+            return file_path(dwarf, unit, index);
+        }
+
+        let origin = entry.attr_value(gimli::DW_AT_abstract_origin)
+                        .or_else(|| entry.attr_value(gimli::DW_AT_specification))?;
+        (unit, entry) = match origin {
+            AttributeValue::UnitRef(offset) => (unit, unit.entry(offset).ok()?),
+            AttributeValue::DebugInfoRef(offset) => {
+                let target = units.iter().find(|candidate| offset.to_unit_offset(&candidate.header).is_some())?;
+                (target, target.entry(offset.to_unit_offset(&target.header)?).ok()?)
+            }
+            _ => return None,
+        };
+    }
+    None
+}
+
+fn file_path(dwarf: &Dwarf<Reader>, unit: &Unit<Reader>, index: u64) -> Option<PathBuf> {
+    let header = unit.line_program.as_ref()?.header();
+    let file = header.file(index)?;
+
+    let mut path = PathBuf::new();
+    if let Some(comp_dir) = &unit.comp_dir { path.push(comp_dir.to_string_lossy().as_ref()); }
+    if let Some(directory) = file.directory(header)
+        && let Ok(directory) = dwarf.attr_string(unit, directory) {
+        path.push(directory.to_string_lossy().as_ref());
+    }
+    path.push(dwarf.attr_string(unit, file.path_name()).ok()?.to_string_lossy().as_ref());
+    Some(normalize(&path))
+}
+
+// "a/b/../c" -> "a/c", without touching the file system (paths are from the build machine)
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => { normalized.pop(); }
+            Component::CurDir => {}
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+pub enum SourceOwner {
+    Package(String),
+    Toolchain(String),
+    Unknown,
+}
+
+// package directories, longest first so nested packages (workspace members) win over their parent
+pub fn package_roots(metadata: &Metadata) -> Vec<(PathBuf, String)> {
+    let mut roots: Vec<(PathBuf, String)> = metadata.packages
+                                                .iter()
+                                                .filter_map(|package| Some((normalize(package.manifest_path.parent()?.as_std_path()), package.id.repr.clone())))
+                                                .collect();
+    roots.sort_by_key(|(root, _)| std::cmp::Reverse(root.components().count()));
+    roots
+}
+
+// registry sources (src/<crate>-<version>/), git checkouts and workspace paths are all under the package's
+// manifest directory; if the paths were remapped, at least <crate>-<version> is usually still in there.
+// The target dir usually is in the workspace root too, but its files (OUT_DIR, ...) aren't the root package's
+pub fn source_owner(path: &Path, metadata: &Metadata, package_roots: &[(PathBuf, String)]) -> SourceOwner {
+    if !path.starts_with(normalize(metadata.target_directory.as_std_path()))
+        && let Some((_, id)) = package_roots.iter().find(|(root, _)| path.starts_with(root)) {
+        return SourceOwner::Package(id.clone());
+    }
+
+    let components: Vec<String> = path.components().map(|component| component.as_os_str().to_string_lossy().into_owned()).collect();
+
+    for component in components.iter().rev() {
+        if let Some(package) = metadata.packages
+                                .iter()
+                                .find(|package| component.strip_prefix(package.name.as_str())
+                                                    .and_then(|rest| rest.strip_prefix('-'))
+                                                    .is_some_and(|version| version == package.version.to_string())) {
+            return SourceOwner::Package(package.id.repr.clone());
+        }
+    }
+
+    // /rustc/<commit>/library/<crate>/... or <sysroot>/lib/rustlib/src/rust/library/<crate>/...
+    match components.iter().position(|component| component == "library") {
+        Some(index) if components.get(index + 2).is_some_and(|component| component == "src") => SourceOwner::Toolchain(components[index + 1].clone()),
+        _ => SourceOwner::Unknown,
+    }
+}
@@ -0,0 +1,252 @@
+use crate::{
+    bloat::{BloatData, Footprint, ambiguous_crates},
+    dwarf::{DwarfAnalyzer, SourceOwner, package_roots, source_owner},
+    error::{Error},
+};
+
+use cargo_metadata::{Metadata};
+use object::{Object, ObjectSection, ObjectSymbol, SectionKind, SymbolKind};
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+// same name cargo bloat uses for symbols it can't attribute to a crate (C code, assembly, ...)
+const UNKNOWN_CRATE: &str = "[Unknown]";
+
+// reads the already built firmware instead of building again through cargo bloat
+pub struct ElfAnalyzer;
+
+impl ElfAnalyzer {
+
+    // deps_dir: cargo's deps directory of the build, to tell versions of the same crate apart by their rlibs
+    pub fn analyze(elf_path: &Path, metadata: &Metadata, deps_dir: Option<&Path>) -> Result<BloatData, Error> {
+
+        let elf_error = |reason: String| Error::Elf { path: elf_path.into(), reason };
+
+        let data = fs::read(elf_path).map_err(|e| elf_error(e.to_string()))?;
+        let file = object::File::parse(&*data).map_err(|e| elf_error(e.to_string()))?;
+        if file.format() != object::BinaryFormat::Elf {
+            return Err(elf_error(format!("not an ELF file but {:?}", file.format())));
+        }
+
+        let mut crates: HashMap<String, u64> = HashMap::new();
+        let mut footprints: HashMap<String, Footprint> = HashMap::new();
+        let mut symbols = SymbolIndex::default();
+
+        // only what ends up as code/data in the image, same as cargo bloat looks at
+        for symbol in file.symbols()
+                        .filter(|symbol| symbol.is_definition())
+                        .filter(|symbol| matches!(symbol.kind(), SymbolKind::Text | SymbolKind::Data)) {
+            let Ok(name) = symbol.name() else { continue };
+            symbols.insert(name);
+            let crate_name = crate_of_symbol(name).unwrap_or_else(|| UNKNOWN_CRATE.into());
+            *crates.entry(crate_name.clone()).or_default() += symbol.size();
+            if let Some(section) = symbol.section_index().and_then(|index| file.section_by_index(index).ok()) {
+                add_to_footprint(footprints.entry(crate_name).or_default(), section.kind(), symbol.size());
+            }
+        }
+
+        if crates.is_empty() {
+            return Err(elf_error("no symbols, stripped?".into()));
+        }
+
+        let total_size = crates.values().sum();
+        let mut bloat_data = BloatData::from_crate_sizes(crates, total_size);
+        let mut image_footprint = Footprint::default();
+        for section in file.sections() { add_to_footprint(&mut image_footprint, section.kind(), section.size()); }
+        bloat_data.set_footprints(footprints, image_footprint);
+
+        // debug info is optional, symbol names alone still work
+        match DwarfAnalyzer::analyze(&file, metadata) {
+            Ok(Some(sizes)) => {
+                if sizes.unattributed > 0 { println!("{} bytes of code not attributable to a crate", sizes.unattributed); }
+                bloat_data.add_dwarf_sizes(sizes);
+            }
+            Ok(None) => println!("No debug info in {}, attributing by symbol names only", elf_path.display()),
+            Err(e) => println!("Cannot read debug info of {}: {}, attributing by symbol names only", elf_path.display(), e),
+        }
+
+        // versions debug info didn't already decide: which of the crate's rlibs have symbols in the firmware
+        if let Some(deps_dir) = deps_dir {
+            let package_roots = package_roots(metadata);
+            for (name, packages) in ambiguous_crates(metadata) {
+                if packages.iter().any(|package| bloat_data.is_exact(package)) { continue; }
+                bloat_data.add_exact_packages(linked_rlib_packages(deps_dir, &name, &symbols, metadata, &package_roots));
+            }
+        }
+
+        Ok(bloat_data)
+    }
+}
+
+// only sections that are loaded (debug info etc. isn't), TLS templates count like .data/.bss
+pub fn add_to_footprint(footprint: &mut Footprint, kind: SectionKind, size: u64) {
+    match kind {
+        SectionKind::Text => footprint.text += size,
+        SectionKind::ReadOnlyData | SectionKind::ReadOnlyDataWithRel | SectionKind::ReadOnlyString => footprint.rodata += size,
+        SectionKind::Data | SectionKind::Tls => footprint.data += size,
+        SectionKind::UninitializedData | SectionKind::UninitializedTls => footprint.bss += size,
+        _ => {}
+    }
+}
+
+// symbol names as they are and the crate disambiguators (v0 mangling) in them
+#[derive(Default)]
+struct SymbolIndex {
+    // crate name -> symbols of that crate
+    names: HashMap<String, HashSet<String>>,
+    // crate name -> disambiguators
+    disambiguators: HashMap<String, HashSet<String>>,
+}
+
+impl SymbolIndex {
+
+    // Rust symbols only, section/local symbols have the same names everywhere
+    fn insert(&mut self, symbol: &str) {
+        if let Some(crate_name) = crate_of_symbol(symbol) {
+            self.names.entry(crate_name).or_default().insert(symbol.to_string());
+        }
+        if let Ok(demangled) = rustc_demangle::try_demangle(symbol) {
+            for (crate_name, disambiguator) in crate_disambiguators(&demangled.to_string()) {
+                self.disambiguators.entry(crate_name).or_default().insert(disambiguator);
+            }
+        }
+    }
+
+    // same symbol of the crate (legacy hashes include the crate instance) or same crate instance (v0 disambiguator);
+    // rlibs also contain copies of std generics, so only the crate's own symbols count
+    fn shares_crate(&self, other: &SymbolIndex, crate_name: &str) -> bool {
+        let shared = |map: &HashMap<String, HashSet<String>>, other_map: &HashMap<String, HashSet<String>>| {
+            match (map.get(crate_name), other_map.get(crate_name)) {
+                (Some(values), Some(other_values)) => !values.is_disjoint(other_values),
+                _ => false,
+            }
+        };
+        shared(&self.disambiguators, &other.disambiguators) || shared(&self.names, &other.names)
+    }
+}
+
+// "heapless[5b1c2d3e4f]::vec::..." -> ("heapless", "5b1c2d3e4f"), only v0 demangling has these
+fn crate_disambiguators(demangled: &str) -> Vec<(String, String)> {
+    let mut found: Vec<(String, String)> = vec![];
+    for (index, _) in demangled.match_indices('[') {
+        let crate_name: String = demangled[..index]
+                                    .chars()
+                                    .rev()
+                                    .take_while(|character| character.is_alphanumeric() || *character == '_')
+                                    .collect::<Vec<char>>()
+                                    .into_iter()
+                                    .rev()
+                                    .collect();
+        let Some(disambiguator) = demangled[index + 1..].split(']').next() else { continue };
+        if !crate_name.is_empty() && !disambiguator.is_empty() && disambiguator.chars().all(|character| character.is_ascii_hexdigit()) {
+            found.push((crate_name, disambiguator.to_string()));
+        }
+    }
+    found
+}
+
+// lib<crate>-<metadata hash>.rlib in the deps dir, the package via the sources in its <crate>-<metadata hash>.d
+fn linked_rlib_packages(deps_dir: &Path, crate_name: &str, firmware_symbols: &SymbolIndex, metadata: &Metadata, package_roots: &[(PathBuf, String)]) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(deps_dir) else { return vec![] };
+    let prefix = format!("lib{}-", crate_name);
+
+    let mut linked: Vec<String> = vec![];
+    for rlib_path in entries
+                        .filter_map(|entry| entry.ok())
+                        .map(|entry| entry.path())
+                        .filter(|path| path.extension().is_some_and(|extension| extension == "rlib"))
+                        .filter(|path| path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with(&prefix))) {
+
+        let Some(package_id) = artifact_package(&rlib_path, metadata, package_roots) else { continue };
+        if linked.contains(&package_id) { continue; }
+        let Some(rlib_symbols) = rlib_symbols(&rlib_path) else { continue };
+        if firmware_symbols.shares_crate(&rlib_symbols, crate_name) {
+            linked.push(package_id);
+        }
+    }
+    linked
+}
+
+// <crate>-<hash>.d next to the rlibs (lib<crate>-<hash>.rlib) and objects (<crate>-<hash>.<cgu>.rcgu.o) of the deps dir;
+// workspace members' sources are relative to the workspace root
+pub fn artifact_package(path: &Path, metadata: &Metadata, package_roots: &[(PathBuf, String)]) -> Option<String> {
+    let stem = artifact_stem(path)?;
+    let dep_info = fs::read_to_string(path.with_file_name(format!("{}.d", stem))).ok()?;
+    dep_info
+        .split_whitespace()
+        .map(|word| word.trim_end_matches(':'))
+        .filter(|word| word.ends_with(".rs"))
+        .find_map(|source| match source_owner(&metadata.workspace_root.as_std_path().join(source), metadata, package_roots) {
+            SourceOwner::Package(id) => Some(id),
+            _ => None,
+        })
+}
+
+// crate of an rlib or object file rustc made, also for the sysroot's rlibs that have no dep-info
+pub fn artifact_crate(path: &Path) -> Option<String> {
+    artifact_stem(path)?.rsplit_once('-').map(|(crate_name, _)| crate_name.to_string())
+}
+
+fn artifact_stem(path: &Path) -> Option<&str> {
+    let file_name = path.file_name()?.to_str()?;
+    let stem = file_name.split('.').next()?;
+    match file_name.ends_with(".rlib") {
+        true => stem.strip_prefix("lib"),
+        false => Some(stem),
+    }
+}
+
+// defined symbols of all objects in the archive
+fn rlib_symbols(rlib_path: &Path) -> Option<SymbolIndex> {
+    let data = fs::read(rlib_path).ok()?;
+    let archive = object::read::archive::ArchiveFile::parse(&*data).ok()?;
+    let mut symbols = SymbolIndex::default();
+    for member in archive.members().filter_map(|member| member.ok()) {
+        let Ok(member_data) = member.data(&*data) else { continue };
+        // lib.rmeta and the like aren't objects
+        let Ok(object_file) = object::File::parse(member_data) else { continue };
+        for symbol in object_file.symbols().filter(|symbol| symbol.is_definition()) {
+            if let Ok(name) = symbol.name() { symbols.insert(name); }
+        }
+    }
+    Some(symbols)
+}
+
+// crate a (legacy or v0 mangled) Rust symbol belongs to, None for everything not mangled by rustc
+pub fn crate_of_symbol(symbol: &str) -> Option<String> {
+    let demangled = rustc_demangle::try_demangle(symbol).ok()?;
+    // alternate format: without hashes and crate disambiguators
+    crate_of_path(&format!("{:#}", demangled))
+}
+
+// "core::fmt::write" -> core, "<alloc::string::String as core::fmt::Write>::write_str" -> alloc,
+// "<u32 as core::fmt::Debug>::fmt" -> core, "<&T as ...>" / "<[T] as ...>" like the inner type
+fn crate_of_path(path: &str) -> Option<String> {
+    match path.strip_prefix('<') {
+        Some(qualified) => {
+            let (self_type, trait_path) = match qualified.split_once(" as ") {
+                Some((self_type, rest)) => (self_type, rest.split('>').next()),
+                None => (qualified.split('>').next().unwrap_or(qualified), None),
+            };
+            let self_type = self_type.trim_start_matches(['&', '*', '[', '(', ' '])
+                                        .trim_start_matches("mut ")
+                                        .trim_start_matches("const ")
+                                        .trim_start_matches("dyn ");
+            // primitives and the like don't have a crate, the trait does
+            match self_type.split_once("::") {
+                Some(_) => crate_of_path(self_type),
+                None => trait_path.and_then(crate_of_path),
+            }
+        }
+        None => {
+            let (crate_name, _) = path.split_once("::")?;
+            let is_identifier = !crate_name.is_empty()
+                                && crate_name.chars().all(|character| character.is_alphanumeric() || character == '_');
+            is_identifier.then(|| crate_name.to_string())
+        }
+    }
+}
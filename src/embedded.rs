@@ -0,0 +1,131 @@
+use crate::{
+    bloat::{BloatData},
+    component::{Component, Property},
+    dwarf::{SourceOwner, normalize, package_roots, source_owner},
+    native::{hex},
+};
+
+use cargo_metadata::{Metadata};
+use sha2::{Digest, Sha256};
+
+use std::{
+    collections::{HashMap},
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime},
+};
+
+// include_str!-ed docs (#![doc = include_str!("../README.md")]) don't end up in the image
+const DOC_EXTENSIONS: [&str; 2] = ["md", "markdown"];
+
+// a non-Rust file that went into compiling a linked crate (include_bytes!/include_str!)
+pub struct EmbeddedFile {
+    pub path: PathBuf,
+    package_id: String,
+    package_name: String,
+    package_version: String,
+    // relative to the package, OUT_DIR/... for files a build script generated
+    relative_path: PathBuf,
+    generated: bool,
+}
+
+impl EmbeddedFile {
+
+    pub fn to_component(&self) -> Component {
+        let relative = &self.relative_path;
+        let file_name = self.path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let hash = fs::read(&self.path).ok().map(|data| hex(&Sha256::digest(&data)));
+
+        let mut component = Component::create_file_component(
+            format!("embedded+{}@{}#{}", self.package_name, self.package_version, relative.display()),
+            &file_name,
+            Some(self.package_id.clone()),
+            hash);
+        component.properties.push(Property::new("arielosbom:path", relative.display()));
+        if self.generated { component.properties.push(Property::new("arielosbom:generated_by_build_script", true)); }
+        component
+    }
+}
+
+// rustc's dep-info files in the deps dir (<crate>-<hash>.d) list every file a crate was compiled from,
+// package id -> files of all its crates; the package is the one of the .rs sources
+pub fn compiled_files(deps_dir: &Path, metadata: &Metadata) -> HashMap<String, Vec<PathBuf>> {
+    let Ok(entries) = fs::read_dir(deps_dir) else { return HashMap::new() };
+    let package_roots = package_roots(metadata);
+
+    // (package id, crate) -> newest dep-info, older ones are from earlier builds
+    let mut newest: HashMap<(String, String), (SystemTime, Vec<PathBuf>)> = HashMap::new();
+    for path in entries
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path())
+                    .filter(|path| path.extension().is_some_and(|extension| extension == "d")) {
+        let Ok(content) = fs::read_to_string(&path) else { continue };
+        let Some(crate_name) = path.file_stem().and_then(|stem| stem.to_str()).and_then(|stem| stem.rsplit_once('-')).map(|(name, _)| name.to_string()) else { continue };
+        let dependencies = dep_info_files(&content, metadata.workspace_root.as_std_path());
+
+        let Some(package_id) = dependencies
+                                .iter()
+                                .filter(|dependency| dependency.extension().is_some_and(|extension| extension == "rs"))
+                                .find_map(|source| match source_owner(source, metadata, &package_roots) {
+                                    SourceOwner::Package(id) => Some(id),
+                                    _ => None,
+                                }) else { continue };
+
+        let modified = fs::metadata(&path).and_then(|metadata| metadata.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+        let key = (package_id, crate_name);
+        if newest.get(&key).is_none_or(|(newest, _)| modified > *newest) {
+            newest.insert(key, (modified, dependencies));
+        }
+    }
+
+    let mut files: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    for ((package_id, _), (_, dependencies)) in newest {
+        files.entry(package_id).or_default().extend(dependencies);
+    }
+    for paths in files.values_mut() {
+        paths.sort();
+        paths.dedup();
+    }
+    files
+}
+
+// the non-Rust files of linked crates, i.e. what include_bytes!/include_str! embedded
+pub fn embedded_files(compiled_files: &HashMap<String, Vec<PathBuf>>, build_dir: &Path, metadata: &Metadata, bloat_data: &BloatData) -> Vec<EmbeddedFile> {
+    let mut files: Vec<EmbeddedFile> = vec![];
+    for package in metadata.packages.iter().filter(|package| bloat_data.is_linked(package)) {
+        let Some(package_root) = package.manifest_path.parent().map(|root| root.as_std_path().to_path_buf()) else { continue };
+        let Some(paths) = compiled_files.get(&package.id.repr) else { continue };
+
+        files.extend(paths
+                        .iter()
+                        .filter(|path| path.extension().is_none_or(|extension| extension != "rs" && !DOC_EXTENSIONS.iter().any(|doc| extension == *doc)))
+                        .map(|path| {
+                            let out_dir_path = out_dir_path(path, build_dir);
+                            EmbeddedFile {
+                                path: path.clone(),
+                                package_id: package.id.repr.clone(),
+                                package_name: package.name.to_string(),
+                                package_version: package.version.to_string(),
+                                generated: out_dir_path.is_some(),
+                                relative_path: out_dir_path.unwrap_or_else(|| path.strip_prefix(&package_root).unwrap_or(path).to_path_buf()),
+                            }
+                        }));
+    }
+    files
+}
+
+// <build dir>/<package>-<hash>/out/x -> OUT_DIR/x, the hash changes with every build
+pub fn out_dir_path(path: &Path, build_dir: &Path) -> Option<PathBuf> {
+    path.strip_prefix(build_dir).ok().map(|path| Path::new("OUT_DIR").join(path.iter().skip(2).collect::<PathBuf>()))
+}
+
+// every dependency also gets a "<path>:" line of its own; spaces in paths are escaped,
+// workspace members' paths are relative to the workspace root (rustc's working dir)
+fn dep_info_files(content: &str, workspace_root: &Path) -> Vec<PathBuf> {
+    content
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.strip_suffix(':'))
+        .map(|path| normalize(&workspace_root.join(path.replace("\\ ", " "))))
+        .collect()
+}
@@ -0,0 +1,141 @@
+use crate::ninja::{NinjaError};
+
+use std::{
+    fmt::{Formatter},
+    io,
+    path::{PathBuf},
+};
+
+// exit codes, also listed in the README:
+//  1   anything not covered below (IO, serialization, ...)
+//  2   invalid arguments/paths given via the command line (clap uses 2 for its own usage errors as well)
+//  3   laze build file missing or not understood
+//  4   firmware ELF, linker map or cargo bloat (fallback) couldn't be analyzed
+//  5   cargo metadata failed or its output doesn't fit
+//  6   Cargo.lock missing or invalid
+//  7   SBOM/report couldn't be written
+#[derive(Debug)]
+pub enum Error {
+    InvalidArgument {
+        reason: String,
+    },
+    BuildSelection {
+        reason: String,
+        available: Vec<String>,
+    },
+    LocalDatabase {
+        name: &'static str,
+        path: PathBuf,
+    },
+    BuildFile {
+        path: PathBuf,
+        reason: String,
+    },
+    BuildFileIo {
+        path: PathBuf,
+        source: io::Error,
+    },
+    CargoBloat {
+        command: String,
+        status: Option<i32>,
+        stderr: String,
+    },
+    CargoBloatOutput {
+        command: String,
+        source: serde_json::Error,
+        stderr: String,
+    },
+    Elf {
+        path: PathBuf,
+        reason: String,
+    },
+    LinkerMap {
+        path: PathBuf,
+        reason: String,
+    },
+    CargoMetadata {
+        manifest_path: PathBuf,
+        source: cargo_metadata::Error,
+    },
+    MetadataContent {
+        reason: String,
+    },
+    Lockfile {
+        path: PathBuf,
+        source: cargo_lock::Error,
+    },
+    Serialize {
+        source: serde_json::Error,
+    },
+    Output {
+        path: PathBuf,
+        source: io::Error,
+    },
+}
+
+impl Error {
+
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Serialize { .. } => 1,
+            Error::InvalidArgument { .. } | Error::BuildSelection { .. } | Error::LocalDatabase { .. } => 2,
+            Error::BuildFile { .. } | Error::BuildFileIo { .. } => 3,
+            Error::CargoBloat { .. } | Error::CargoBloatOutput { .. } | Error::Elf { .. } | Error::LinkerMap { .. } => 4,
+            Error::CargoMetadata { .. } | Error::MetadataContent { .. } => 5,
+            Error::Lockfile { .. } => 6,
+            Error::Output { .. } => 7,
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Error::InvalidArgument { reason } => write!(f, "Invalid argument: {}", reason),
+            Error::BuildSelection { reason, available } => write!(f, "{}, available app/builder pairs:\n    {}", reason, available.join("\n    ")),
+            Error::LocalDatabase { name, path } => write!(f, "Cannot open {} at {}", name, path.display()),
+            Error::BuildFile { path, reason } => write!(f, "Cannot use build file {}: {}", path.display(), reason),
+            Error::BuildFileIo { path, source } => write!(f, "Cannot read build file {}: {}", path.display(), source),
+            Error::CargoBloat { command, status, stderr } => write!(f, "cargo bloat failed (exit status {}):\n{}\nstderr:\n{}",
+                match status {
+                    Some(status) => status.to_string(),
+                    None => "none".into()
+                },
+                command,
+                stderr
+            ),
+            Error::CargoBloatOutput { command, source, stderr } => write!(f, "Could not deserialize cargo bloat data: {}\n{}\nstderr:\n{}", source, command, stderr),
+            Error::Elf { path, reason } => write!(f, "Cannot analyze firmware ELF {}: {}", path.display(), reason),
+            Error::LinkerMap { path, reason } => write!(f, "Cannot read linker map {}: {}", path.display(), reason),
+            Error::CargoMetadata { manifest_path, source } => match source {
+                cargo_metadata::Error::CargoMetadata { stderr } => write!(f, "cargo metadata failed for {}:\n{}", manifest_path.display(), stderr),
+                other => write!(f, "cargo metadata failed for {}: {}", manifest_path.display(), other),
+            },
+            Error::MetadataContent { reason } => write!(f, "Unexpected cargo metadata content: {}", reason),
+            Error::Lockfile { path, source } => write!(f, "Cannot load lock file {}: {}", path.display(), source),
+            Error::Serialize { source } => write!(f, "Could not serialize data: {}", source),
+            Error::Output { path, source } => write!(f, "Could not write {}: {}", path.display(), source),
+        }
+    }
+}
+
+impl From<NinjaError> for Error {
+    fn from(e: NinjaError) -> Error {
+        match e {
+            NinjaError::Io { path, source } => Error::BuildFileIo { path, source },
+            NinjaError::Syntax { path, line, reason } => Error::BuildFile { path, reason: format!("line {}: {}", line, reason) },
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::BuildFileIo { source, .. } | Error::Output { source, .. } => Some(source),
+            Error::CargoBloatOutput { source, .. } | Error::Serialize { source } => Some(source),
+            Error::CargoMetadata { source, .. } => Some(source),
+            Error::Lockfile { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
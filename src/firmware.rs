@@ -0,0 +1,112 @@
+use crate::{
+    component::{Component, Dependency, Property},
+    native::{hex},
+    target::{TargetInfo},
+};
+
+use cargo_metadata::{DependencyKind, Metadata, Package, TargetKind};
+use sha2::{Digest, Sha256, Sha512};
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+// flashable images objcopy makes out of the ELF, next to it with the same stem
+const IMAGE_EXTENSIONS: [&str; 2] = ["bin", "hex"];
+
+// the linked firmware, root of the SBOM's dependency tree
+pub struct Firmware {
+    pub elf_path: PathBuf,
+    images: Vec<PathBuf>,
+    target: Option<String>,
+    // id of the device component of the MCU
+    device: Option<String>,
+    package: Package,
+    dependencies: Vec<Dependency>,
+}
+
+impl Firmware {
+
+    pub fn id(&self) -> String {
+        format!("firmware+{}@{}", self.package.name, self.package.version)
+    }
+
+    // the ELF first, the images after it with the ELF as their parent
+    pub fn to_components(&self) -> Vec<Component> {
+        let Ok(data) = fs::read(&self.elf_path) else { return vec![] };
+
+        let mut firmware = Component::create_firmware_component(
+            self.id(),
+            &self.package,
+            file_name(&self.elf_path),
+            vec![hex(&Sha256::digest(&data)), hex(&Sha512::digest(&data))],
+            self.dependencies.clone());
+        firmware.properties.push(Property::new("arielosbom:size_bytes", data.len()));
+        if let Some(target) = &self.target { firmware.properties.push(Property::new("arielosbom:target", target)); }
+        if let Some(device) = &self.device { firmware.properties.push(Property::new("arielosbom:device", device)); }
+        firmware.properties.push(Property::new("arielosbom:path", self.elf_path.display()));
+
+        let mut components = vec![firmware];
+        for image in self.images.iter() {
+            let Ok(data) = fs::read(image) else { continue };
+            let name = file_name(image);
+            let mut component = Component::create_file_component(format!("{}#{}", self.id(), name), &name, Some(self.id()), Some(hex(&Sha256::digest(&data))));
+            component.identifiers.push(hex(&Sha512::digest(&data)));
+            component.properties.push(Property::new("arielosbom:size_bytes", data.len()));
+            component.properties.push(Property::new("arielosbom:path", image.display()));
+            components.push(component);
+        }
+        components
+    }
+}
+
+// the package that owns the ELF: the bin target of the same name, otherwise the resolve root;
+// its direct dependencies are the firmware's
+pub fn firmware(elf_path: &Path, target_info: &TargetInfo, metadata: &Metadata) -> Option<Firmware> {
+    let stem = elf_path.file_stem()?.to_string_lossy().into_owned();
+    let workspace_members = metadata.workspace_packages();
+
+    let package = workspace_members
+                    .iter()
+                    .find(|package| package.targets.iter().any(|target| target.kind.contains(&TargetKind::Bin) && target.name == stem))
+                    .copied()
+                    .or_else(|| metadata.root_package())
+                    .or_else(|| (workspace_members.len() == 1).then(|| workspace_members[0]));
+    let Some(package) = package else {
+        println!("Cannot tell which package firmware {} was built from, no root component", elf_path.display());
+        return None;
+    };
+
+    let dependencies: Vec<Dependency> = metadata.resolve
+                                            .iter()
+                                            .flat_map(|resolve| resolve.nodes.iter())
+                                            .filter(|node| node.id == package.id)
+                                            .flat_map(|node| node.deps.iter())
+                                            // dev-dependencies are only for the package's tests/examples/benches
+                                            .filter(|dep| dep.dep_kinds.is_empty() || dep.dep_kinds.iter().any(|info| info.kind != DependencyKind::Development))
+                                            .map(|dep| Dependency {
+                                                id: dep.pkg.repr.clone(),
+                                                build: dep.dep_kinds.iter().any(|info| info.kind == DependencyKind::Build),
+                                            })
+                                            .collect();
+
+    let images: Vec<PathBuf> = IMAGE_EXTENSIONS
+                                .iter()
+                                .map(|extension| elf_path.with_extension(extension))
+                                .filter(|path| path.is_file() && path != elf_path)
+                                .collect();
+
+    Some(Firmware {
+        elf_path: elf_path.to_path_buf(),
+        images,
+        target: target_info.triple.clone(),
+        device: target_info.device_id(),
+        package: package.clone(),
+        dependencies,
+    })
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+}
@@ -0,0 +1,134 @@
+use crate::{
+    bloat::{BloatData},
+    component::{Component, Property},
+    embedded::{out_dir_path},
+    native::{BuildScriptRun, hex},
+};
+
+use cargo_metadata::{DependencyKind, Metadata, Package};
+use semver::{Version};
+use sha2::{Digest, Sha256};
+
+use std::{
+    collections::{HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+// build dependencies that generate code, in case the generated files don't say what made them
+const GENERATOR_CRATES: [&str; 12] = ["bindgen", "svd2rust", "cbindgen", "prost-build", "tonic-build", "lalrpop", "phf_codegen", "vergen", "built", "uniffi_build", "cxx-build", "embuild"];
+
+// Rust sources a crate's build script wrote into OUT_DIR and that were compiled into the crate
+pub struct GeneratedCode {
+    package_id: String,
+    package_name: String,
+    package_version: Version,
+    // (path, OUT_DIR/..., generator named in the file)
+    files: Vec<(PathBuf, PathBuf, Option<String>)>,
+    // generator build dependencies with versions
+    generator_crates: Vec<String>,
+    inputs: Vec<PathBuf>,
+    env_inputs: Vec<String>,
+}
+
+impl GeneratedCode {
+
+    // the build script (child of its package) and the generated files (children of the build script)
+    pub fn to_components(&self) -> Vec<Component> {
+        let build_script_id = format!("build-script+{}@{}", self.package_name, self.package_version);
+        let mut generators: Vec<String> = self.files.iter().filter_map(|(_, _, generator)| generator.clone()).collect();
+        generators.sort();
+        generators.dedup();
+        if generators.is_empty() { generators = self.generator_crates.clone(); }
+
+        let mut build_script = Component::create_build_script_component(
+                                build_script_id.clone(),
+                                &format!("{} build script", self.package_name),
+                                self.package_version.clone(),
+                                self.package_id.clone());
+        if !generators.is_empty() { build_script.properties.push(Property::new("arielosbom:generator", generators.join(", "))); }
+        if !self.inputs.is_empty() {
+            build_script.properties.push(Property::new("arielosbom:inputs", self.inputs.iter().map(|input| input.display().to_string()).collect::<Vec<String>>().join(", ")));
+        }
+        if !self.env_inputs.is_empty() { build_script.properties.push(Property::new("arielosbom:env_inputs", self.env_inputs.join(", "))); }
+
+        let mut components = vec![build_script];
+        for (path, relative_path, generator) in self.files.iter() {
+            let file_name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            let hash = fs::read(path).ok().map(|data| hex(&Sha256::digest(&data)));
+            let mut component = Component::create_file_component(
+                format!("generated+{}@{}#{}", self.package_name, self.package_version, relative_path.display()),
+                &file_name,
+                Some(build_script_id.clone()),
+                hash);
+            component.properties.push(Property::new("arielosbom:path", relative_path.display()));
+            if let Some(generator) = generator { component.properties.push(Property::new("arielosbom:generator", generator)); }
+            components.push(component);
+        }
+        components
+    }
+}
+
+// .rs files of linked crates' dep-info that are in a build script's OUT_DIR
+pub fn generated_code(compiled_files: &HashMap<String, Vec<PathBuf>>, build_dir: &Path, runs: &[BuildScriptRun], metadata: &Metadata, bloat_data: &BloatData) -> Vec<GeneratedCode> {
+    let mut generated: Vec<GeneratedCode> = vec![];
+    for package in metadata.packages.iter().filter(|package| bloat_data.is_linked(package)) {
+        let Some(paths) = compiled_files.get(&package.id.repr) else { continue };
+        let files: Vec<(PathBuf, PathBuf, Option<String>)> = paths
+                                                                .iter()
+                                                                .filter(|path| path.extension().is_some_and(|extension| extension == "rs"))
+                                                                .filter_map(|path| Some((path.clone(), out_dir_path(path, build_dir)?, generator_of(path))))
+                                                                .collect();
+        if files.is_empty() { continue; }
+
+        let (inputs, env_inputs) = match runs.iter().find(|run| run.package_id == package.id.repr) {
+            Some(run) => {
+                let (inputs, env_inputs) = run.inputs();
+                (inputs.to_vec(), env_inputs.to_vec())
+            }
+            None => (vec![], vec![]),
+        };
+
+        generated.push(GeneratedCode {
+            package_id: package.id.repr.clone(),
+            package_name: package.name.to_string(),
+            package_version: package.version.clone(),
+            files,
+            generator_crates: generator_crates(package, metadata),
+            inputs,
+            env_inputs,
+        });
+    }
+    generated
+}
+
+// "/* automatically generated by rust-bindgen 0.69.4 */", "... (generated using svd2rust v0.33.0 ...",
+// "// This file is @generated by prost-build." in the first lines
+fn generator_of(path: &Path) -> Option<String> {
+    let content = fs::read_to_string(path).ok()?;
+    content
+        .lines()
+        .take(10)
+        .find_map(|line| {
+            let lowercase = line.to_ascii_lowercase();
+            let index = ["generated by ", "generated using "].iter().find_map(|marker| lowercase.find(marker).map(|index| index + marker.len()))?;
+            let mut words = line[index..].split_whitespace().map(|word| word.trim_matches(|character: char| !character.is_alphanumeric() && character != '-' && character != '_' && character != '.').trim_end_matches('.'));
+            let tool = words.next().filter(|tool| !tool.is_empty())?;
+            match words.next().filter(|version| version.trim_start_matches('v').starts_with(|character: char| character.is_ascii_digit())) {
+                Some(version) => Some(format!("{} {}", tool, version)),
+                None => Some(tool.to_string()),
+            }
+        })
+}
+
+// code generating crates among the package's build dependencies, "bindgen 0.69.4"
+fn generator_crates(package: &Package, metadata: &Metadata) -> Vec<String> {
+    let Some(node) = metadata.resolve.as_ref().and_then(|resolve| resolve.nodes.iter().find(|node| node.id == package.id)) else { return vec![] };
+    node.deps
+        .iter()
+        .filter(|dep| dep.dep_kinds.iter().any(|info| info.kind == DependencyKind::Build))
+        .filter_map(|dep| metadata.packages.iter().find(|package| package.id == dep.pkg))
+        .filter(|package| GENERATOR_CRATES.contains(&package.name.as_str()))
+        .map(|package| format!("{} {}", package.name, package.version))
+        .collect()
+}
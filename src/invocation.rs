@@ -0,0 +1,442 @@
+use crate::ninja::{BuildEdge, NinjaFile};
+
+use std::{
+    path::{Path, PathBuf},
+    process::{Command},
+};
+
+// the cargo build command laze runs, taken apart so other cargo subcommands (bloat, metadata, ...)
+// can be run with the same environment and arguments
+#[derive(Clone, Debug)]
+pub struct CargoInvocation {
+    // relative to the project root, from "cd <dir> && ..." in front of cargo
+    pub working_dir: Option<PathBuf>,
+    pub env: Vec<(String, String)>,
+    pub toolchain: Option<String>,
+    // before the subcommand, e.g. -Z flags
+    pub global_args: Vec<String>,
+    // after "build"
+    pub args: Vec<String>,
+    // outputs of the build statement, i.e. the firmware
+    pub outputs: Vec<PathBuf>,
+    // from laze's output dir layout: <build dir>/bin/<builder>/<app>/
+    pub app: Option<String>,
+    pub builder: Option<String>,
+}
+
+// the parts of the build that decide which dependencies are resolved
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BuildOptions {
+    pub toolchain: Option<String>,
+    pub target: Option<String>,
+    pub features: Vec<String>,
+    pub all_features: bool,
+    pub no_default_features: bool,
+    // -Z flags without the -Z, e.g. build-std=core,alloc
+    pub unstable_flags: Vec<String>,
+    // effective rustflags of the build, --filter-platform evaluates cfg(...) target dependencies with them
+    // (e.g. Ariel OS' cfg(context = "...") board dependencies)
+    pub rustflags: Vec<String>,
+}
+
+impl BuildOptions {
+
+    // crates given to -Z build-std, None if the prebuilt std is used
+    pub fn build_std(&self) -> Option<Vec<String>> {
+        self.unstable_flags
+            .iter()
+            .find_map(|flag| match flag.split_once('=') {
+                Some(("build-std", crates)) => Some(crates.split(',').map(|name| name.trim().to_string()).collect()),
+                None if flag == "build-std" => Some(vec!["std".into()]),
+                _ => None,
+            })
+    }
+}
+
+impl CargoInvocation {
+
+    // first cargo build in the shell command
+    pub fn from_command(command: &str) -> Option<CargoInvocation> {
+
+        let mut working_dir: Option<PathBuf> = None;
+
+        for segment in split_shell_segments(command) {
+
+            if segment.first().is_some_and(|word| word == "cd") {
+                if let Some(dir) = segment.get(1) {
+                    working_dir = Some(match working_dir {
+                        Some(current) => current.join(dir),
+                        None => PathBuf::from(dir),
+                    });
+                }
+                continue;
+            }
+
+            let mut words = segment.iter().peekable();
+            words.next_if(|word| *word == "env");
+
+            let mut env: Vec<(String, String)> = vec![];
+            while let Some(assignment) = words.next_if(|word| is_env_assignment(word)) {
+                let (name, value) = assignment.split_once('=').unwrap();
+                env.push((name.into(), value.into()));
+            }
+
+            if words.next_if(|word| Path::new(word).file_name().is_some_and(|name| name == "cargo")).is_none() {
+                continue;
+            }
+
+            let toolchain = words.next_if(|word| word.starts_with('+')).map(|word| word[1..].to_string());
+
+            let mut global_args: Vec<String> = vec![];
+            let mut subcommand: Option<String> = None;
+            while let Some(word) = words.next() {
+                if !word.starts_with('-') {
+                    subcommand = Some(word.clone());
+                    break;
+                }
+                global_args.push(word.clone());
+                // global flags with a separate value
+                if ["-Z", "-C", "--config", "--color"].contains(&word.as_str())
+                    && let Some(value) = words.next() {
+                    global_args.push(value.clone());
+                }
+            }
+
+            match subcommand.as_deref() {
+                Some("build") | Some("b") => {},
+                _ => continue,
+            }
+
+            return Some(CargoInvocation {
+                working_dir,
+                env,
+                toolchain,
+                global_args,
+                args: words.cloned().collect(),
+                outputs: vec![],
+                app: None,
+                builder: None,
+            });
+        }
+
+        None
+    }
+
+    // plain cargo projects (no laze): arguments as they'd be given to cargo build, optionally starting with +toolchain
+    pub fn from_build_args(args: &[String], env: Vec<(String, String)>, working_dir: Option<PathBuf>) -> CargoInvocation {
+        let (toolchain, args) = match args.split_first() {
+            Some((first, rest)) if first.starts_with('+') => (Some(first[1..].to_string()), rest),
+            _ => (None, args),
+        };
+
+        let mut invocation = CargoInvocation {
+            working_dir,
+            env,
+            toolchain,
+            global_args: vec![],
+            args: args.to_vec(),
+            outputs: vec![],
+            app: None,
+            builder: None,
+        };
+        invocation.app = invocation.arg_values("--bin").into_iter().next();
+        invocation
+    }
+
+    pub fn from_build_edge(edge: &BuildEdge) -> Option<CargoInvocation> {
+        let mut invocation = CargoInvocation::from_command(edge.command()?)?;
+        invocation.outputs = edge.outputs.iter().map(PathBuf::from).collect();
+        if let Some((app, builder)) = invocation.outputs.iter().find_map(|output| laze_app_and_builder(output)) {
+            invocation.app = Some(app);
+            invocation.builder = Some(builder);
+        }
+        Some(invocation)
+    }
+
+    pub fn matches(&self, app: Option<&str>, builder: Option<&str>) -> bool {
+        app.is_none_or(|app| self.app.as_deref() == Some(app))
+            && builder.is_none_or(|builder| self.builder.as_deref() == Some(builder))
+    }
+
+    // --target or CARGO_BUILD_TARGET, None means host
+    pub fn target_triple(&self) -> Option<String> {
+        self.arg_values("--target")
+            .into_iter()
+            .next_back()
+            .or_else(|| self.env_value("CARGO_BUILD_TARGET").map(|target| target.to_string()))
+    }
+
+    // rustflags: from the environment or the cargo config, see linker::rustflags
+    pub fn build_options(&self, rustflags: Vec<String>) -> BuildOptions {
+        let mut unstable_flags: Vec<String> = values_of(&self.global_args, "-Z");
+        unstable_flags.append(&mut values_of(&self.args, "-Z"));
+        if let Some(build_std) = self.env_value("CARGO_UNSTABLE_BUILD_STD")
+            && !unstable_flags.iter().any(|flag| flag.starts_with("build-std")) {
+            unstable_flags.push(format!("build-std={}", build_std));
+        }
+
+        let mut features: Vec<String> = self.arg_values("--features");
+        features.append(&mut self.arg_values("-F"));
+
+        BuildOptions {
+            toolchain: self.toolchain.clone(),
+            target: self.target_triple(),
+            features: features
+                        .iter()
+                        .flat_map(|value| value.split([',', ' ']))
+                        .filter(|feature| !feature.is_empty())
+                        .map(|feature| feature.to_string())
+                        .collect(),
+            all_features: self.args.iter().any(|arg| arg == "--all-features"),
+            no_default_features: self.args.iter().any(|arg| arg == "--no-default-features"),
+            unstable_flags,
+            rustflags,
+        }
+    }
+
+    // values of "--flag value" and "--flag=value"
+    pub fn arg_values(&self, flag: &str) -> Vec<String> {
+        values_of(&self.args, flag)
+    }
+
+    // where the linked firmware should be: laze's build outputs, otherwise cargo's artifact for --bin
+    pub fn firmware_paths(&self, project_root: &Path, target_directory: &Path) -> Vec<PathBuf> {
+        if !self.outputs.is_empty() {
+            return self.outputs.iter().map(|output| project_root.join(output)).collect();
+        }
+
+        let Some(bin) = self.arg_values("--bin").into_iter().next_back() else { return vec![] };
+
+        vec![self.artifact_dir(project_root, target_directory).join(bin)]
+    }
+
+    // <target dir>/[<triple>/]<profile dir>, cargo's default target dir is the one cargo metadata reports
+    pub fn artifact_dir(&self, project_root: &Path, target_directory: &Path) -> PathBuf {
+        let profile = match self.arg_values("--profile").into_iter().next_back() {
+            Some(profile) if profile == "dev" || profile == "test" => "debug".to_string(),
+            Some(profile) if profile == "bench" => "release".to_string(),
+            Some(profile) => profile,
+            None if self.args.iter().any(|arg| arg == "--release" || arg == "-r") => "release".into(),
+            None => "debug".into(),
+        };
+
+        let given_target_dir = self.arg_values("--target-dir")
+                                    .into_iter()
+                                    .next_back()
+                                    .or_else(|| self.env_value("CARGO_TARGET_DIR").map(|dir| dir.to_string()));
+        let mut path = match given_target_dir {
+            Some(dir) => {
+                let working_dir = match &self.working_dir {
+                    Some(working_dir) => project_root.join(working_dir),
+                    None => project_root.to_path_buf(),
+                };
+                working_dir.join(dir)
+            }
+            None => target_directory.to_path_buf(),
+        };
+
+        if let Some(target) = self.target_triple() { path.push(target); }
+        path.push(profile);
+        path
+    }
+
+    pub fn env_value(&self, name: &str) -> Option<&str> {
+        self.env
+            .iter()
+            .rev()
+            .find(|(env_name, _)| env_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn app_and_builder(&self) -> String {
+        format!("{}/{}",
+            self.app.as_deref().unwrap_or("<unknown app>"),
+            self.builder.as_deref().unwrap_or("<unknown builder>")
+        )
+    }
+
+    // all build statements that run a cargo build, in file order
+    pub fn find_in_ninja(ninja_file: &NinjaFile) -> Vec<CargoInvocation> {
+        ninja_file.builds
+            .iter()
+            .filter_map(CargoInvocation::from_build_edge)
+            .collect()
+    }
+
+    // same environment, toolchain and arguments, just another subcommand
+    pub fn command(&self, project_root: &Path, subcommand: &str, extra_args: &[&str]) -> Command {
+        let mut command = self.tool_command(project_root, "cargo");
+        if let Some(toolchain) = &self.toolchain {
+            command.arg(format!("+{}", toolchain));
+        }
+        command.args(&self.global_args);
+        command.arg(subcommand);
+        command.args(extra_args);
+        command.args(&self.args);
+        command
+    }
+
+    // other tools (rustc, linker, ...) in the build's directory/environment, rustup picks the build's toolchain
+    pub fn tool_command(&self, project_root: &Path, program: impl AsRef<std::ffi::OsStr>) -> Command {
+        let mut command = Command::new(program);
+        command.current_dir(match &self.working_dir {
+            Some(working_dir) => project_root.join(working_dir),
+            None => project_root.to_path_buf(),
+        });
+        command.envs(self.env.iter().map(|(name, value)| (name, value)));
+        if let Some(toolchain) = &self.toolchain {
+            command.env("RUSTUP_TOOLCHAIN", toolchain);
+        }
+        command
+    }
+
+    // CARGO_ENCODED_RUSTFLAGS or RUSTFLAGS, which replace all rustflags of the config; None if cargo takes them from
+    // the config (CARGO_TARGET_<TRIPLE>_RUSTFLAGS and CARGO_BUILD_RUSTFLAGS are config values, see linker::rustflags)
+    pub fn env_rustflags(&self) -> Option<Vec<String>> {
+        if let Some(flags) = self.env_value("CARGO_ENCODED_RUSTFLAGS") {
+            return Some(flags.split('\x1f').filter(|flag| !flag.is_empty()).map(|flag| flag.to_string()).collect());
+        }
+        self.env_value("RUSTFLAGS").map(|flags| flags.split_whitespace().map(|flag| flag.to_string()).collect())
+    }
+
+    // -C linker from RUSTFLAGS or CARGO_TARGET_<TRIPLE>_LINKER
+    pub fn linker(&self) -> Option<String> {
+        if let Some(target) = self.target_triple()
+            && let Some(linker) = self.env_value(&format!("CARGO_TARGET_{}_LINKER", target.to_uppercase().replace(['-', '.'], "_"))) {
+            return Some(linker.to_string());
+        }
+        values_of(&self.env_rustflags().unwrap_or_default(), "-C")
+            .into_iter()
+            .filter_map(|option| option.strip_prefix("linker=").map(|linker| linker.to_string()))
+            .next_back()
+    }
+}
+
+// "--flag value", "--flag=value" and for short flags also "-Fvalue"
+fn values_of(args: &[String], flag: &str) -> Vec<String> {
+    let short = !flag.starts_with("--");
+    let mut values: Vec<String> = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            if let Some(value) = args.next() { values.push(value.clone()); }
+        } else if let Some(rest) = arg.strip_prefix(flag) {
+            match rest.strip_prefix('=') {
+                Some(value) => values.push(value.into()),
+                None if short => values.push(rest.into()),
+                None => {}
+            }
+        }
+    }
+    values
+}
+
+fn laze_app_and_builder(output: &Path) -> Option<(String, String)> {
+    let components: Vec<&str> = output.iter().filter_map(|component| component.to_str()).collect();
+    let bin_index = components.iter().rposition(|component| *component == "bin")?;
+    match (components.get(bin_index + 1), components.get(bin_index + 2)) {
+        // at least one more component for the actual file
+        (Some(builder), Some(app)) if components.len() > bin_index + 3 => Some((app.to_string(), builder.to_string())),
+        _ => None
+    }
+}
+
+fn is_env_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => !name.is_empty()
+                            && !name.starts_with(|character: char| character.is_ascii_digit())
+                            && name.chars().all(|character| character.is_ascii_alphanumeric() || character == '_'),
+        None => false
+    }
+}
+
+// splits a shell command into simple commands (at &&, ||, ;, |) and those into words,
+// with quotes and backslash escapes resolved; no expansions
+fn split_shell_segments(command: &str) -> Vec<Vec<String>> {
+
+    let mut segments: Vec<Vec<String>> = vec![vec![]];
+    let mut word: Option<String> = None;
+    let mut characters = command.chars().peekable();
+
+    let finish_word = |word: &mut Option<String>, segments: &mut Vec<Vec<String>>| {
+        if let Some(word) = word.take() { segments.last_mut().unwrap().push(word); }
+    };
+
+    while let Some(character) = characters.next() {
+        match character {
+            ' ' | '\t' | '\n' => finish_word(&mut word, &mut segments),
+            '&' | '|' | ';' => {
+                finish_word(&mut word, &mut segments);
+                characters.next_if(|next| *next == character);
+                if segments.last().is_some_and(|segment| !segment.is_empty()) { segments.push(vec![]); }
+            }
+            '\'' => {
+                let quoted: String = characters.by_ref().take_while(|next| *next != '\'').collect();
+                word.get_or_insert_default().push_str(&quoted);
+            }
+            '"' => {
+                let current = word.get_or_insert_default();
+                while let Some(next) = characters.next() {
+                    match next {
+                        '"' => break,
+                        '\\' => match characters.next_if(|escaped| "\"\\$`".contains(*escaped)) {
+                            Some(escaped) => current.push(escaped),
+                            None => current.push('\\'),
+                        },
+                        other => current.push(other),
+                    }
+                }
+            }
+            '\\' => {
+                if let Some(escaped) = characters.next() { word.get_or_insert_default().push(escaped); }
+            }
+            other => word.get_or_insert_default().push(other),
+        }
+    }
+    finish_word(&mut word, &mut segments);
+
+    segments.retain(|segment| !segment.is_empty());
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(segments: &[&[&str]]) -> Vec<Vec<String>> {
+        segments.iter().map(|segment| segment.iter().map(|word| word.to_string()).collect()).collect()
+    }
+
+    #[test]
+    fn shell_segments_and_quoting() {
+        let segments = split_shell_segments(r#"cd app && A="x y" B='$z "q"' C=a\ b cargo build; echo 1 || true | cat"#);
+        assert_eq!(segments, words(&[
+            &["cd", "app"],
+            &["A=x y", r#"B=$z "q""#, "C=a b", "cargo", "build"],
+            &["echo", "1"],
+            &["true"],
+            &["cat"],
+        ]));
+    }
+
+    #[test]
+    fn escapes_in_double_quotes() {
+        let segments = split_shell_segments(r#"RUSTFLAGS="--cfg context=\"nrf52840\" -C x=\$y \a" cargo"#);
+        assert_eq!(segments, words(&[&[r#"RUSTFLAGS=--cfg context="nrf52840" -C x=$y \a"#, "cargo"]]));
+    }
+
+    #[test]
+    fn cargo_build_from_command() {
+        let invocation = CargoInvocation::from_command(
+            r#"cd app && cd sub && CARGO_BUILD_TARGET=thumbv7em-none-eabihf RUSTFLAGS="-Clink-arg=--nmagic" cargo +nightly -Z build-std=core build --release --features=a && cp x y"#
+        ).unwrap();
+        assert_eq!(invocation.working_dir, Some(PathBuf::from("app/sub")));
+        assert_eq!(invocation.toolchain.as_deref(), Some("nightly"));
+        assert_eq!(invocation.global_args, vec!["-Z", "build-std=core"]);
+        assert_eq!(invocation.args, vec!["--release", "--features=a"]);
+        assert_eq!(invocation.target_triple().as_deref(), Some("thumbv7em-none-eabihf"));
+        assert_eq!(invocation.env_rustflags(), Some(vec!["-Clink-arg=--nmagic".to_string()]));
+
+        assert!(CargoInvocation::from_command("cargo metadata && cargo fmt").is_none());
+    }
+}
@@ -0,0 +1,119 @@
+use serde::{Deserialize};
+use serde_yaml::{Value};
+
+use std::{
+    collections::{HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+const PROJECT_FILE: &str = "laze-project.yml";
+// in the project's subdirs
+const SUBDIR_FILE: &str = "laze.yml";
+// where laze checks out imported projects (git/download), e.g. Ariel OS itself for an application
+const IMPORTS_DIR: &str = "build/imports";
+// laze's implicit root of all contexts
+const DEFAULT_CONTEXT: &str = "default";
+
+// a laze file can have several YAML documents, everything else in it (modules, apps, ...) is irrelevant here
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct LazeFile {
+    contexts: Vec<LazeContext>,
+    builders: Vec<LazeContext>,
+    subdirs: Vec<String>,
+    imports: Vec<LazeImport>,
+}
+
+// contexts and builders look the same, builders are the leaves (boards)
+#[derive(Clone, Deserialize)]
+pub struct LazeContext {
+    pub name: String,
+    #[serde(default)]
+    parent: Option<String>,
+    #[serde(default)]
+    env: HashMap<String, Value>,
+}
+
+impl LazeContext {
+
+    // plain string variables only, e.g. PROBE_RS_CHIP; lists (RUSTFLAGS, ...) get merged by laze
+    pub fn env_value(&self, name: &str) -> Option<&str> {
+        self.env.get(name).and_then(|value| value.as_str())
+    }
+}
+
+// only local imports, the others end up in the imports dir
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct LazeImport {
+    path: Option<String>,
+}
+
+// contexts and builders of the laze project at the root and of everything it pulls in
+#[derive(Default)]
+pub struct LazeProject {
+    contexts: HashMap<String, LazeContext>,
+    builders: HashMap<String, LazeContext>,
+}
+
+impl LazeProject {
+
+    // no laze files (plain cargo, or not checked out) -> empty project
+    pub fn read(project_root: &Path) -> LazeProject {
+        let mut files: Vec<PathBuf> = vec![project_root.join(PROJECT_FILE)];
+        if let Ok(entries) = fs::read_dir(project_root.join(IMPORTS_DIR)) {
+            files.extend(entries.filter_map(|entry| entry.ok()).map(|entry| entry.path().join(PROJECT_FILE)));
+        }
+
+        let mut project = LazeProject::default();
+        let mut index = 0;
+        while let Some(path) = files.get(index).cloned() {
+            index += 1;
+            let Ok(content) = fs::read_to_string(&path) else { continue };
+            let dir = path.parent().unwrap_or(project_root);
+            for document in serde_yaml::Deserializer::from_str(&content) {
+                let file = match LazeFile::deserialize(document) {
+                    Ok(file) => file,
+                    Err(e) => {
+                        println!("Cannot read laze file {}: {}", path.display(), e);
+                        break;
+                    }
+                };
+                // first definition wins, like the project's own over an import's
+                for context in file.contexts { project.contexts.entry(context.name.clone()).or_insert(context); }
+                for builder in file.builders { project.builders.entry(builder.name.clone()).or_insert(builder); }
+                let included = file.subdirs
+                                .iter()
+                                .map(|subdir| dir.join(subdir).join(SUBDIR_FILE))
+                                .chain(file.imports.iter().filter_map(|import| import.path.as_ref()).map(|import| dir.join(import).join(PROJECT_FILE)));
+                for included in included {
+                    if !files.contains(&included) { files.push(included); }
+                }
+            }
+        }
+        project
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contexts.is_empty() && self.builders.is_empty()
+    }
+
+    // the builder and the contexts it inherits from, builder first; empty for an unknown builder
+    pub fn context_chain(&self, builder: &str) -> Vec<&LazeContext> {
+        let mut chain: Vec<&LazeContext> = vec![];
+        let mut next = self.builders.get(builder).or_else(|| self.contexts.get(builder));
+        while let Some(context) = next {
+            if chain.iter().any(|known| known.name == context.name) { break; }
+            chain.push(context);
+            if context.name == DEFAULT_CONTEXT { break; }
+            let parent = context.parent.as_deref().unwrap_or(DEFAULT_CONTEXT);
+            next = self.contexts.get(parent).or_else(|| self.builders.get(parent));
+        }
+        chain
+    }
+}
+
+pub fn is_default_context(name: &str) -> bool {
+    name == DEFAULT_CONTEXT
+}
@@ -0,0 +1,361 @@
+use crate::{
+    component::{Component, Property},
+    dwarf::{SourceOwner, package_roots, source_owner},
+    invocation::{CargoInvocation},
+    native::{BuildScriptRun, hex},
+    toolchain::{command_output},
+};
+
+use cargo_metadata::{Metadata};
+use sha2::{Digest, Sha256};
+
+use std::{
+    collections::{HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+// a linker script (-T/INCLUDE) of the final link and the package that provides it
+pub struct LinkerScript {
+    pub path: PathBuf,
+    // (package id, name, version)
+    provider: Option<(String, String, String)>,
+    // generated or copied into OUT_DIR by the provider's build script
+    generated: bool,
+    included_by: Option<String>,
+}
+
+impl LinkerScript {
+
+    pub fn to_component(&self) -> Component {
+        let file_name = self.path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let id = match &self.provider {
+            Some((_, name, version)) => format!("linker-script+{}@{}#{}", name, version, file_name),
+            None => format!("linker-script+{}", self.path.display()),
+        };
+        let hash = fs::read(&self.path).ok().map(|data| hex(&Sha256::digest(&data)));
+
+        let mut component = Component::create_file_component(id, &file_name, self.provider.as_ref().map(|(id, _, _)| id.clone()), hash);
+        component.properties.push(Property::new("arielosbom:path", self.path.display()));
+        if self.generated { component.properties.push(Property::new("arielosbom:generated_by_build_script", true)); }
+        if let Some(included_by) = &self.included_by { component.properties.push(Property::new("arielosbom:included_by", included_by)); }
+        component
+    }
+}
+
+// -T scripts from the build's rustflags and the firmware package's rustc-link-arg, plus what they INCLUDE;
+// looked up like the linker does: in the working dir (cargo runs rustc in the workspace root), then the -L paths
+pub fn linker_scripts(project_root: &Path, invocation: &CargoInvocation, runs: &[BuildScriptRun], metadata: &Metadata) -> Vec<LinkerScript> {
+    let rustflags = rustflags(project_root, invocation);
+    let link_args = link_args(&rustflags, runs);
+
+    let mut search_paths: Vec<PathBuf> = vec![metadata.workspace_root.as_std_path().to_path_buf()];
+    search_paths.extend(rustflag_search_paths(&rustflags));
+    search_paths.extend(runs.iter().flat_map(|run| run.search_paths().iter().cloned()));
+
+    let package_roots = package_roots(metadata);
+    let provider = |path: &Path| -> (Option<(String, String, String)>, bool) {
+        let package = |id: &String| metadata.packages
+                                        .iter()
+                                        .find(|package| package.id.repr == *id)
+                                        .map(|package| (id.clone(), package.name.to_string(), package.version.to_string()));
+        if let Some(run) = runs.iter().find(|run| path.starts_with(&run.out_dir)) {
+            return (package(&run.package_id), true);
+        }
+        match source_owner(path, metadata, &package_roots) {
+            SourceOwner::Package(id) => (package(&id), false),
+            _ => (None, false),
+        }
+    };
+
+    let mut scripts: Vec<LinkerScript> = vec![];
+    let mut queue: Vec<(String, Option<String>)> = script_arguments(&link_args).into_iter().map(|name| (name, None)).collect();
+    queue.reverse();
+    while let Some((name, included_by)) = queue.pop() {
+        let Some(path) = find_script(&name, &search_paths) else {
+            println!("Linker script {} not found", name);
+            continue;
+        };
+        if scripts.iter().any(|script| script.path == path) { continue; }
+
+        let file_name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let mut includes: Vec<(String, Option<String>)> = fs::read_to_string(&path)
+                                                            .map(|content| includes(&content))
+                                                            .unwrap_or_default()
+                                                            .into_iter()
+                                                            .map(|include| (include, Some(file_name.clone())))
+                                                            .collect();
+        includes.reverse();
+        queue.extend(includes);
+
+        let (provider, generated) = provider(&path);
+        scripts.push(LinkerScript { path, provider, generated, included_by });
+    }
+    scripts
+}
+
+// the environment's (RUSTFLAGS etc.), otherwise the ones from the cargo config
+pub fn rustflags(project_root: &Path, invocation: &CargoInvocation) -> Vec<String> {
+    invocation.env_rustflags().unwrap_or_else(|| config_rustflags(project_root, invocation))
+}
+
+// what the final link gets from rustflags and the firmware package's build script
+pub fn link_args(rustflags: &[String], runs: &[BuildScriptRun]) -> Vec<String> {
+    let mut link_args: Vec<String> = rustflag_link_args(rustflags);
+    link_args.extend(runs.iter().flat_map(|run| run.link_args().iter().cloned()));
+    link_args
+}
+
+// -C link-arg=X, -C link-args="X Y" (also -Clink-arg=X)
+fn rustflag_link_args(rustflags: &[String]) -> Vec<String> {
+    let mut link_args: Vec<String> = vec![];
+    let mut flags = rustflags.iter();
+    while let Some(flag) = flags.next() {
+        let option = match flag.as_str() {
+            "-C" => flags.next().map(|option| option.as_str()),
+            _ => flag.strip_prefix("-C"),
+        };
+        match option.and_then(|option| option.split_once('=')) {
+            Some(("link-arg", argument)) => link_args.push(argument.into()),
+            Some(("link-args", arguments)) => link_args.extend(arguments.split_whitespace().map(|argument| argument.to_string())),
+            _ => {}
+        }
+    }
+    link_args
+}
+
+// -L [KIND=]PATH
+fn rustflag_search_paths(rustflags: &[String]) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = vec![];
+    let mut flags = rustflags.iter();
+    while let Some(flag) = flags.next() {
+        let value = match flag.as_str() {
+            "-L" => flags.next().map(|value| value.as_str()),
+            _ => flag.strip_prefix("-L"),
+        };
+        if let Some(value) = value {
+            paths.push(PathBuf::from(value.split_once('=').map(|(_, path)| path).unwrap_or(value)));
+        }
+    }
+    paths
+}
+
+// -Tscript, -T script, --script=script, -Wl,-T,script
+fn script_arguments(link_args: &[String]) -> Vec<String> {
+    let arguments: Vec<&str> = link_args
+                                .iter()
+                                .flat_map(|argument| match argument.strip_prefix("-Wl,") {
+                                    Some(arguments) => arguments.split(',').collect(),
+                                    None => vec![argument.as_str()],
+                                })
+                                .collect();
+    let mut scripts: Vec<String> = vec![];
+    let mut arguments = arguments.into_iter();
+    while let Some(argument) = arguments.next() {
+        let script = match argument {
+            "-T" | "--script" => arguments.next(),
+            _ => argument.strip_prefix("--script=").or_else(|| argument.strip_prefix("-T")),
+        };
+        if let Some(script) = script.filter(|script| !script.is_empty()) { scripts.push(script.into()); }
+    }
+    scripts
+}
+
+// INCLUDE file / INCLUDE "file" of a linker script, anywhere (also within SECTIONS/MEMORY), not in comments
+fn includes(content: &str) -> Vec<String> {
+    let mut uncommented = String::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("/*") {
+        uncommented.push_str(&rest[..start]);
+        rest = rest[start..].find("*/").map(|end| &rest[start + end + 2..]).unwrap_or("");
+    }
+    uncommented.push_str(rest);
+
+    let tokens: Vec<&str> = uncommented.split_whitespace().collect();
+    tokens
+        .windows(2)
+        .filter(|pair| pair[0] == "INCLUDE")
+        .map(|pair| pair[1].trim_end_matches(';').trim_matches('"').to_string())
+        .filter(|file| !file.is_empty())
+        .collect()
+}
+
+fn find_script(name: &str, search_paths: &[PathBuf]) -> Option<PathBuf> {
+    let path = Path::new(name);
+    if path.is_absolute() { return path.is_file().then(|| path.to_path_buf()); }
+    search_paths.iter().map(|dir| dir.join(path)).find(|path| path.is_file())
+}
+
+// .cargo/config.toml (and the older .cargo/config) files from the working dir up and in CARGO_HOME, most specific first
+fn cargo_configs(project_root: &Path, invocation: &CargoInvocation) -> Vec<toml::Table> {
+    let working_dir = match &invocation.working_dir {
+        Some(working_dir) => project_root.join(working_dir),
+        None => project_root.to_path_buf(),
+    };
+    let cargo_home = invocation.env_value("CARGO_HOME")
+                        .map(PathBuf::from)
+                        .or_else(|| std::env::var_os("CARGO_HOME").map(PathBuf::from))
+                        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cargo")));
+
+    working_dir
+        .ancestors()
+        .map(|dir| dir.join(".cargo"))
+        .chain(cargo_home)
+        .flat_map(|dir| [dir.join("config.toml"), dir.join("config")])
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .filter_map(|content| content.parse::<toml::Table>().ok())
+        .collect()
+}
+
+// --target/CARGO_BUILD_TARGET, otherwise build.target of the cargo config (embedded projects often set it there)
+pub fn target_triple(project_root: &Path, invocation: &CargoInvocation) -> Option<String> {
+    invocation.target_triple().or_else(|| cargo_configs(project_root, invocation)
+                                            .iter()
+                                            .find_map(|config| config.get("build")?.get("target")?.as_str().map(|target| target.to_string())))
+}
+
+// no RUSTFLAGS in the environment: like cargo, all target.<triple>.rustflags and the target.'cfg(...)'.rustflags whose
+// cfg the target matches (plus CARGO_TARGET_<TRIPLE>_RUSTFLAGS); if there are none, build.rustflags (plus CARGO_BUILD_RUSTFLAGS)
+fn config_rustflags(project_root: &Path, invocation: &CargoInvocation) -> Vec<String> {
+    let configs = cargo_configs(project_root, invocation);
+
+    let flags_of = |value: Option<&toml::Value>| -> Vec<String> {
+        match value {
+            Some(toml::Value::String(flags)) => flags.split_whitespace().map(|flag| flag.to_string()).collect(),
+            Some(toml::Value::Array(flags)) => flags.iter().filter_map(|flag| flag.as_str()).map(|flag| flag.to_string()).collect(),
+            _ => vec![],
+        }
+    };
+    let env_flags = |name: &str| -> Vec<String> {
+        invocation.env_value(name).map(|flags| flags.split_whitespace().map(|flag| flag.to_string()).collect()).unwrap_or_default()
+    };
+
+    // without a target cargo builds for the host
+    let target = target_triple(project_root, invocation).or_else(|| host_triple(project_root, invocation));
+    let target_cfgs: Option<HashSet<String>> = configs
+                                                .iter()
+                                                .filter_map(|config| config.get("target")?.as_table())
+                                                .any(|targets| targets.keys().any(|key| key.starts_with("cfg(")))
+                                                .then(|| target_cfgs(project_root, invocation, target.as_deref()))
+                                                .flatten();
+    let mut target_flags: Vec<String> = vec![];
+    for config in configs.iter() {
+        let Some(targets) = config.get("target").and_then(|targets| targets.as_table()) else { continue };
+        for (key, table) in targets.iter() {
+            let applies = match key.strip_prefix("cfg(").and_then(|cfg| cfg.strip_suffix(')')) {
+                Some(cfg) => target_cfgs.as_ref().is_some_and(|cfgs| cfg_matches(cfg, cfgs)),
+                None => Some(key) == target.as_ref(),
+            };
+            if applies { target_flags.extend(flags_of(table.get("rustflags"))); }
+        }
+    }
+    if let Some(target) = &target {
+        target_flags.extend(env_flags(&format!("CARGO_TARGET_{}_RUSTFLAGS", target.to_uppercase().replace(['-', '.'], "_"))));
+    }
+    if !target_flags.is_empty() { return target_flags; }
+
+    let mut build_flags: Vec<String> = configs
+                                        .iter()
+                                        .flat_map(|config| flags_of(config.get("build").and_then(|build| build.get("rustflags"))))
+                                        .collect();
+    build_flags.extend(env_flags("CARGO_BUILD_RUSTFLAGS"));
+    build_flags
+}
+
+fn host_triple(project_root: &Path, invocation: &CargoInvocation) -> Option<String> {
+    let version = command_output(invocation.tool_command(project_root, "rustc").arg("-vV"))?;
+    version.lines().find_map(|line| line.strip_prefix("host: ")).map(|host| host.trim().to_string())
+}
+
+// rustc's cfgs for the target: names (unix, ...) and key="value" (target_arch="arm", ...)
+fn target_cfgs(project_root: &Path, invocation: &CargoInvocation, target: Option<&str>) -> Option<HashSet<String>> {
+    let mut command = invocation.tool_command(project_root, "rustc");
+    command.args(["--print", "cfg"]);
+    if let Some(target) = target { command.args(["--target", target]); }
+    Some(command_output(&mut command)?.lines().map(|line| line.trim().to_string()).collect())
+}
+
+// cfg expression (what's inside cfg(...)): all(...), any(...), not(...), name and key = "value"
+fn cfg_matches(expression: &str, cfgs: &HashSet<String>) -> bool {
+    let expression = expression.trim();
+    let call = |name: &str| expression
+                                .strip_prefix(name)
+                                .map(|rest| rest.trim_start())
+                                .and_then(|rest| rest.strip_prefix('('))
+                                .and_then(|rest| rest.strip_suffix(')'));
+    if let Some(arguments) = call("all") { return cfg_arguments(arguments).iter().all(|argument| cfg_matches(argument, cfgs)); }
+    if let Some(arguments) = call("any") { return cfg_arguments(arguments).iter().any(|argument| cfg_matches(argument, cfgs)); }
+    if let Some(argument) = call("not") { return !cfg_matches(argument, cfgs); }
+    match expression.split_once('=') {
+        Some((key, value)) => cfgs.contains(&format!("{}=\"{}\"", key.trim(), value.trim().trim_matches('"'))),
+        None => cfgs.contains(expression),
+    }
+}
+
+// the comma separated arguments of all(...)/any(...), not split inside nested parentheses or strings
+fn cfg_arguments(arguments: &str) -> Vec<&str> {
+    let mut split: Vec<&str> = vec![];
+    let (mut depth, mut quoted, mut start) = (0, false, 0);
+    for (index, character) in arguments.char_indices() {
+        match character {
+            '"' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => depth -= 1,
+            ',' if !quoted && depth == 0 => {
+                split.push(&arguments[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    split.push(&arguments[start..]);
+    split.into_iter().filter(|argument| !argument.trim().is_empty()).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn script_arguments_all_forms() {
+        let link_args = strings(&["-Tlink.x", "-T", "memory.x", "--script=device.x", "--script", "defmt.x", "-Wl,-T,isr.x", "-Wl,--gc-sections", "-nostartfiles"]);
+        assert_eq!(script_arguments(&link_args), strings(&["link.x", "memory.x", "device.x", "defmt.x", "isr.x"]));
+        // a trailing -T without a script is no script
+        assert!(script_arguments(&strings(&["-T"])).is_empty());
+    }
+
+    #[test]
+    fn rustflag_link_args_all_forms() {
+        let rustflags = strings(&["-C", "link-arg=-Tlink.x", "-Clink-arg=--nmagic", "-C", "link-args=-Map=fw.map -Tdefmt.x", "-C", "opt-level=s", "-Ctarget-cpu=cortex-m4"]);
+        assert_eq!(rustflag_link_args(&rustflags), strings(&["-Tlink.x", "--nmagic", "-Map=fw.map", "-Tdefmt.x"]));
+    }
+
+    #[test]
+    fn includes_anywhere_but_comments() {
+        let script = "INCLUDE memory.x\n\
+                      SECTIONS {\n\tINCLUDE\t\"sections.x\";\n}\n\
+                      MEMORY { INCLUDE ram.x }\n\
+                      /* INCLUDE old.x */\n\
+                      EXCLUDE_FILE INCLUDED\n";
+        assert_eq!(includes(script), strings(&["memory.x", "sections.x", "ram.x"]));
+    }
+
+    #[test]
+    fn cfg_expressions() {
+        let cfgs: HashSet<String> = strings(&["target_os=\"none\"", "target_arch=\"arm\"", "target_has_atomic=\"32\"", "debug_assertions"]).into_iter().collect();
+        assert!(cfg_matches("target_os = \"none\"", &cfgs));
+        assert!(cfg_matches("debug_assertions", &cfgs));
+        assert!(!cfg_matches("unix", &cfgs));
+        assert!(cfg_matches("all(target_arch = \"arm\", target_os = \"none\")", &cfgs));
+        assert!(!cfg_matches("all(target_arch = \"arm\", unix)", &cfgs));
+        assert!(cfg_matches("any(unix, all(target_arch = \"arm\", not(target_has_atomic = \"64\")))", &cfgs));
+        assert!(!cfg_matches("not(target_os = \"none\")", &cfgs));
+        // a comma within a value doesn't split the arguments
+        assert!(!cfg_matches("any(target_os = \"a,none\")", &cfgs));
+    }
+}
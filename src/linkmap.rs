@@ -0,0 +1,479 @@
+use crate::{
+    bloat::{Footprint},
+    dwarf::{normalize, package_roots},
+    elf::{add_to_footprint, artifact_crate, artifact_package},
+    error::{Error},
+    invocation::{CargoInvocation},
+    linker::{link_args, rustflags},
+    native::{BuildScriptRun, NativeLibrary, archive_objects, object_name},
+};
+
+use cargo_metadata::{Metadata};
+use object::{Object, ObjectSection, SectionKind};
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+// what a linker map says went into an output section, one entry per input section
+pub struct InputSection {
+    pub output_section: String,
+    // object file or archive; *fill*, <internal> and <linker script> for what the linker added itself
+    pub file: String,
+    // object in the archive
+    pub member: Option<String>,
+    pub size: u64,
+}
+
+pub struct LinkerMap {
+    pub path: PathBuf,
+    // output section -> size
+    pub output_sections: HashMap<String, u64>,
+    pub sections: Vec<InputSection>,
+}
+
+// per component id, with what no component accounts for (linker padding, libgcc, crt objects, ...) by file name
+pub struct MapFootprints {
+    pub path: PathBuf,
+    pub components: HashMap<String, Footprint>,
+    pub unattributed: Vec<(String, Footprint)>,
+    pub total: Footprint,
+    // the package whose single object has all Rust code of the firmware (LTO)
+    pub merged_into: Option<String>,
+}
+
+impl MapFootprints {
+
+    // the map can't tell the crates of a merged object apart, the symbols can: they get their share of it,
+    // scaled down evenly where the symbols add up to more than the object has
+    pub fn split_merged(&mut self, symbol_footprints: &HashMap<String, Footprint>) {
+        let Some(merged_into) = self.merged_into.clone() else { return };
+        let Some(merged) = self.components.get(&merged_into).copied() else { return };
+        let shares: Vec<(&String, &Footprint)> = symbol_footprints
+                                                    .iter()
+                                                    .filter(|(id, _)| **id != merged_into && !self.components.contains_key(*id))
+                                                    .collect();
+        if shares.is_empty() { return; }
+        println!("All Rust code is in one object of the linker map (LTO?), splitting it by the symbols");
+        let wanted = shares.iter().fold(Footprint::default(), |sum, (_, footprint)| Footprint {
+            text: sum.text + footprint.text,
+            rodata: sum.rodata + footprint.rodata,
+            data: sum.data + footprint.data,
+            bss: sum.bss + footprint.bss,
+        });
+        let mut rest = merged;
+        for (id, footprint) in shares {
+            let share = Footprint {
+                text: scaled_share(footprint.text, wanted.text, merged.text),
+                rodata: scaled_share(footprint.rodata, wanted.rodata, merged.rodata),
+                data: scaled_share(footprint.data, wanted.data, merged.data),
+                bss: scaled_share(footprint.bss, wanted.bss, merged.bss),
+            };
+            rest = Footprint {
+                text: rest.text - share.text,
+                rodata: rest.rodata - share.rodata,
+                data: rest.data - share.data,
+                bss: rest.bss - share.bss,
+            };
+            self.components.insert(id.clone(), share);
+        }
+        self.components.insert(merged_into, rest);
+    }
+}
+
+// rounded down, so the shares never add up to more than is available
+fn scaled_share(size: u64, wanted: u64, available: u64) -> u64 {
+    match wanted <= available {
+        true => size,
+        false => (size as u128 * available as u128 / wanted as u128) as u64,
+    }
+}
+
+impl LinkerMap {
+
+    // GNU ld ("Linker script and memory map") and LLD (VMA/LMA/Size/Align/Out/In/Symbol table) formats
+    pub fn read(path: &Path) -> Result<LinkerMap, Error> {
+        let map_error = |reason: String| Error::LinkerMap { path: path.into(), reason };
+
+        let content = fs::read_to_string(path).map_err(|e| map_error(e.to_string()))?;
+        let (output_sections, sections) = match content.lines().position(is_lld_header) {
+            Some(header) => lld_sections(&content, header),
+            None if content.lines().any(|line| line.starts_with("Linker script and memory map")) => gnu_sections(&content),
+            None => return Err(map_error("neither a GNU ld nor an LLD map".into())),
+        };
+        if sections.is_empty() {
+            return Err(map_error("no input sections".into()));
+        }
+        Ok(LinkerMap { path: path.into(), output_sections, sections })
+    }
+
+    // a map from an earlier link has other section sizes (the map is written before the ELF, so no timestamps)
+    pub fn matches_elf(&self, elf_path: &Path) -> bool {
+        let Ok(data) = fs::read(elf_path) else { return false };
+        let Ok(file) = object::File::parse(&*data) else { return false };
+        file.sections()
+            .filter_map(|section| Some((section.name().ok()?.to_string(), section.size())))
+            .all(|(name, size)| self.output_sections.get(&name).is_none_or(|map_size| *map_size == size))
+    }
+
+    // input files -> components: native libraries by their archive (also bundled into the -sys crate's rlib),
+    // crates by the dep-info of their rlib/objects, the standard library by crate name;
+    // generic code counts for the crate it was instantiated in, with LTO everything for the firmware crate (see split_merged)
+    pub fn footprints(&self, elf_path: Option<&Path>, metadata: &Metadata, libraries: &[NativeLibrary], std_ids: &HashMap<String, String>) -> MapFootprints {
+        let section_kinds = elf_path.map(section_kinds).unwrap_or_default();
+        let package_roots = package_roots(metadata);
+        let library_objects: Vec<(&NativeLibrary, Vec<String>)> = libraries
+                                                                    .iter()
+                                                                    .filter_map(|library| Some((library, archive_objects(library.path.as_ref()?)?)))
+                                                                    .collect();
+
+        let owner = |file: &str, member: Option<&String>| -> Result<String, String> {
+            let path = normalize(&metadata.workspace_root.as_std_path().join(file));
+            if let Some(library) = libraries.iter().find(|library| library.path.as_ref() == Some(&path)) {
+                return Ok(library.id());
+            }
+            if let Some(package_id) = artifact_package(&path, metadata, &package_roots) {
+                let native_member = member.filter(|member| !member.ends_with(".rcgu.o"));
+                if let Some((library, _)) = native_member.and_then(|member| library_objects
+                                                                                .iter()
+                                                                                .filter(|(library, _)| library.package_id == package_id)
+                                                                                .find(|(_, objects)| objects.iter().any(|object| object == object_name(member)))) {
+                    return Ok(library.id());
+                }
+                return Ok(package_id);
+            }
+            // rlibs by crate (dependencies of std without rust-src), everything else by file name
+            let crate_name = path.extension().is_some_and(|extension| extension == "rlib").then(|| artifact_crate(&path)).flatten();
+            match crate_name {
+                Some(crate_name) => std_ids.get(&crate_name).cloned().ok_or(crate_name),
+                None => Err(path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_else(|| file.into())),
+            }
+        };
+
+        let mut owners: HashMap<(&String, Option<&String>), Result<String, String>> = HashMap::new();
+        let mut components: HashMap<String, Footprint> = HashMap::new();
+        let mut unattributed: HashMap<String, Footprint> = HashMap::new();
+        let mut total = Footprint::default();
+        for section in self.sections.iter() {
+            let kind = section_kinds.get(&section.output_section).copied().unwrap_or_else(|| section_kind_by_name(&section.output_section));
+            let mut footprint = Footprint::default();
+            add_to_footprint(&mut footprint, kind, section.size);
+            if footprint == Footprint::default() { continue; }
+
+            total += footprint;
+            let owner = owners.entry((&section.file, section.member.as_ref())).or_insert_with(|| owner(&section.file, section.member.as_ref()));
+            match owner {
+                Ok(id) => *components.entry(id.clone()).or_default() += footprint,
+                Err(name) => *unattributed.entry(name.clone()).or_default() += footprint,
+            }
+        }
+
+        let mut unattributed: Vec<(String, Footprint)> = unattributed.into_iter().collect();
+        unattributed.sort_by(|a, b| b.1.flash().cmp(&a.1.flash()).then_with(|| a.0.cmp(&b.0)));
+
+        // LTO: one package, and its code in one object file
+        let mut package_files: HashMap<&String, HashSet<(&String, Option<&String>)>> = HashMap::new();
+        for (file, owner) in owners.iter() {
+            if let Ok(id) = owner
+                && metadata.packages.iter().any(|package| package.id.repr == *id) {
+                package_files.entry(id).or_default().insert(*file);
+            }
+        }
+        let merged_into = match package_files.into_iter().collect::<Vec<_>>().as_slice() {
+            [(id, files)] if files.len() == 1 => Some((*id).clone()),
+            _ => None,
+        };
+
+        MapFootprints { path: self.path.clone(), components, unattributed, total, merged_into }
+    }
+}
+
+// -Map=file, -Map file, --Map=file, -Wl,-Map=file/-Wl,-Map,file in the link args (relative to the workspace root,
+// where the linker runs), otherwise <firmware>.map next to the ELF
+pub fn find_linker_map(project_root: &Path, invocation: &CargoInvocation, runs: &[BuildScriptRun], metadata: &Metadata, elf_path: &Path) -> Option<PathBuf> {
+    let link_args = link_args(&rustflags(project_root, invocation), runs);
+    let arguments: Vec<&str> = link_args
+                                .iter()
+                                .flat_map(|argument| match argument.strip_prefix("-Wl,") {
+                                    Some(arguments) => arguments.split(',').collect(),
+                                    None => vec![argument.as_str()],
+                                })
+                                .collect();
+    let mut candidates: Vec<PathBuf> = vec![];
+    let mut arguments = arguments.into_iter();
+    while let Some(argument) = arguments.next() {
+        let map = match argument {
+            "-Map" | "--Map" => arguments.next(),
+            _ => argument.strip_prefix("-Map=").or_else(|| argument.strip_prefix("--Map=")),
+        };
+        if let Some(map) = map { candidates.push(metadata.workspace_root.as_std_path().join(map)); }
+    }
+    candidates.push(elf_path.with_extension("map"));
+    if let Some(file_name) = elf_path.file_name() {
+        candidates.push(elf_path.with_file_name(format!("{}.map", file_name.to_string_lossy())));
+    }
+    candidates.into_iter().find(|path| path.is_file())
+}
+
+// "     VMA      LMA     Size Align Out     In      Symbol" (older LLD: "Address Size Align Out In Symbol")
+fn is_lld_header(line: &str) -> bool {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    words.ends_with(&["Out", "In", "Symbol"]) && words.contains(&"Size")
+}
+
+// columns up to Out are numbers (hex without 0x), the indentation of the rest says what it is:
+// output section at Out, input section at In (8 further), symbols and assignments at Symbol
+fn lld_sections(content: &str, header_index: usize) -> (HashMap<String, u64>, Vec<InputSection>) {
+    let mut lines = content.lines().skip(header_index);
+    let Some(header) = lines.next() else { return Default::default() };
+    let Some(out_column) = header.find("Out") else { return Default::default() };
+    let header_words: Vec<&str> = header[..out_column].split_whitespace().collect();
+    let Some(size_index) = header_words.iter().position(|word| *word == "Size") else { return Default::default() };
+
+    let mut output_sections: HashMap<String, u64> = HashMap::new();
+    let mut sections: Vec<InputSection> = vec![];
+    let mut output_section: Option<String> = None;
+    for line in lines {
+        let Some((columns, rest_start)) = split_columns(line, header_words.len()) else { continue };
+        let Some(size) = u64::from_str_radix(columns[size_index], 16).ok() else { continue };
+        let rest = line[rest_start..].trim_end();
+        match rest_start.saturating_sub(out_column) {
+            0 => {
+                output_section = is_section_name(rest).then(|| rest.to_string());
+                if let Some(name) = &output_section { output_sections.insert(name.clone(), size); }
+            }
+            8 => {
+                let (Some(output_section), true) = (&output_section, size > 0) else { continue };
+                let (file, member) = match rest.rsplit_once(":(") {
+                    Some((file, _)) => split_member(file),
+                    // LONG(...), BYTE(...) and the like from the linker script; assignments have no size
+                    None => ("<linker script>".into(), None),
+                };
+                sections.push(InputSection { output_section: output_section.clone(), file, member, size });
+            }
+            _ => {}
+        }
+    }
+    (output_sections, sections)
+}
+
+// output sections start in the first column, input sections (and *fill*) after one space;
+// names too long for their column put address, size and file on the next line
+fn gnu_sections(content: &str) -> (HashMap<String, u64>, Vec<InputSection>) {
+    let mut output_sections: HashMap<String, u64> = HashMap::new();
+    let mut sections: Vec<InputSection> = vec![];
+    let mut output_section: Option<String> = None;
+    let mut pending_input = false;
+    let mut pending_output = false;
+    let output_size = |output_sections: &mut HashMap<String, u64>, name: &Option<String>, size: Option<&&str>| {
+        let (Some(name), Some(size)) = (name, size.and_then(|size| size.strip_prefix("0x")).and_then(|size| u64::from_str_radix(size, 16).ok())) else { return };
+        output_sections.insert(name.clone(), size);
+    };
+
+    let mut push = |output_section: &Option<String>, values: &[&str]| {
+        let (Some(output_section), [_, size, file @ ..]) = (output_section, values) else { return };
+        let Some(size) = size.strip_prefix("0x").and_then(|size| u64::from_str_radix(size, 16).ok()) else { return };
+        if size == 0 { return; }
+        let (file, member) = match file.is_empty() {
+            true => ("*fill*".into(), None),
+            false => split_member(&file.join(" ")),
+        };
+        sections.push(InputSection { output_section: output_section.clone(), file, member, size });
+    };
+
+    for line in content.lines().skip_while(|line| !line.starts_with("Linker script and memory map")).skip(1) {
+        if line.starts_with("OUTPUT(") { break; }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some(first) = words.first() else { continue };
+        let indent = line.len() - line.trim_start().len();
+
+        if pending_input || pending_output {
+            let continued = indent > 1 && first.starts_with("0x");
+            match (pending_input, continued) {
+                (true, true) => push(&output_section, &words),
+                (false, true) => output_size(&mut output_sections, &output_section, words.get(1)),
+                _ => {}
+            }
+            (pending_input, pending_output) = (false, false);
+            if continued { continue; }
+        }
+        match indent {
+            0 if ["LOAD", "START", "END"].contains(first) => {}
+            0 => {
+                output_section = is_section_name(first).then(|| first.to_string());
+                match words.len() {
+                    1 => pending_output = true,
+                    _ => output_size(&mut output_sections, &output_section, words.get(2)),
+                }
+            }
+            1 if *first == "*fill*" => push(&output_section, &words[1..]),
+            1 if first.starts_with('*') => {}
+            1 if words.len() == 1 => pending_input = true,
+            1 => push(&output_section, &words[1..]),
+            _ => {}
+        }
+    }
+    (output_sections, sections)
+}
+
+// the first n whitespace separated columns and where the rest of the line starts
+fn split_columns(line: &str, n: usize) -> Option<(Vec<&str>, usize)> {
+    let mut columns: Vec<&str> = vec![];
+    let mut rest = line;
+    for _ in 0..n {
+        let trimmed = rest.trim_start();
+        let end = trimmed.find(char::is_whitespace)?;
+        columns.push(&trimmed[..end]);
+        rest = &trimmed[end..];
+    }
+    Some((columns, line.len() - rest.trim_start().len()))
+}
+
+// "/path/libfoo.a(foo.o)" -> archive and member
+fn split_member(file: &str) -> (String, Option<String>) {
+    match file.strip_suffix(')').and_then(|file| file.rsplit_once('(')) {
+        Some((archive, member)) => (archive.into(), Some(member.into())),
+        None => (file.into(), None),
+    }
+}
+
+// not PROVIDE(...), assignments and the like
+fn is_section_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(['(', '=', ' '])
+}
+
+fn section_kinds(elf_path: &Path) -> HashMap<String, SectionKind> {
+    let Ok(data) = fs::read(elf_path) else { return HashMap::new() };
+    let Ok(file) = object::File::parse(&*data) else { return HashMap::new() };
+    file.sections()
+        .filter_map(|section| Some((section.name().ok()?.to_string(), section.kind())))
+        .collect()
+}
+
+// without the ELF: the usual names
+fn section_kind_by_name(name: &str) -> SectionKind {
+    let starts_with = |prefixes: &[&str]| prefixes.iter().any(|prefix| name.starts_with(prefix));
+    match name {
+        _ if starts_with(&[".text", ".vector_table", ".init", ".fini"]) => SectionKind::Text,
+        _ if starts_with(&[".rodata", ".ARM.ex", ".eh_frame", ".gcc_except_table"]) => SectionKind::ReadOnlyData,
+        _ if starts_with(&[".data", ".tdata", ".got"]) => SectionKind::Data,
+        _ if starts_with(&[".bss", ".tbss", ".uninit", ".noinit"]) => SectionKind::UninitializedData,
+        _ => SectionKind::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(sections: &[InputSection]) -> Vec<(&str, &str, Option<&str>, u64)> {
+        sections
+            .iter()
+            .map(|section| (section.output_section.as_str(), section.file.as_str(), section.member.as_deref(), section.size))
+            .collect()
+    }
+
+    const GNU_MAP: &str = "\
+Archive member included to satisfy reference by file (symbol)
+
+/usr/lib/libc.a(memcpy.o)     /tmp/fw/main.o (memcpy)
+
+Linker script and memory map
+
+LOAD /tmp/fw/main.o
+LOAD /usr/lib/libc.a
+                0x0000000000000000                . = 0x0
+
+.text           0x0000000000000000      0x1a4
+ *(.text .text.*)
+ .text          0x0000000000000000       0x40 /tmp/fw/main.o
+                0x0000000000000000                main
+ .text._ZN4core3fmt9Formatter3pad17h0123456789abcdefE
+                0x0000000000000040       0x64 /tmp/fw/deps/libcore-0123.rlib(core-0123.core.a1b2-cgu.0.rcgu.o)
+                0x0000000000000040                _ZN4core3fmt9Formatter3pad17h0123456789abcdefE
+ *fill*         0x00000000000000a4        0x4 
+ .text          0x00000000000000a8       0xfc /usr/lib/libc.a(memcpy.o)
+ .text.unused   0x00000000000001a4        0x0 /tmp/fw/main.o
+
+.rodata.a_rather_long_output_section_name
+                0x00000000000001a4       0x10
+ .rodata        0x00000000000001a4       0x10 /tmp/fw/main.o
+
+.bss            0x0000000020000000       0x20
+ .bss           0x0000000020000000       0x20 /tmp/fw/main.o
+OUTPUT(/tmp/fw/firmware elf32-littlearm)
+";
+
+    // (no line continuation, it would eat the header's indentation)
+    const LLD_MAP: &str = "
+     VMA      LMA     Size Align Out     In      Symbol
+       0        0      1a8     4 .text
+       0        0       40     4         /tmp/fw/main.o:(.text)
+       0        0        0     1                 main
+      40       40       64     4         /tmp/fw/deps/libcore-0123.rlib(core-0123.core.a1b2-cgu.0.rcgu.o):(.text._ZN4core3fmt9Formatter3pad17h0123456789abcdefE)
+      a4       a4        4     4         <internal>:(.text.__veneer)
+      a8       a8       fc     4         /usr/lib/libc.a(memcpy.o):(.text)
+     1a4      1a4        4     1         LONG(0xdeadbeef)
+     1a8      1a8        0     1 end = .
+20000000 20000000       20     4 .bss
+20000000 20000000       20     4         /tmp/fw/main.o:(.bss)
+";
+
+    #[test]
+    fn gnu_map() {
+        let (output_sections, sections) = gnu_sections(GNU_MAP);
+        assert_eq!(output_sections.get(".text"), Some(&0x1a4));
+        // long name, size on the next line
+        assert_eq!(output_sections.get(".rodata.a_rather_long_output_section_name"), Some(&0x10));
+        assert_eq!(output_sections.get(".bss"), Some(&0x20));
+        assert_eq!(entries(&sections), vec![
+            (".text", "/tmp/fw/main.o", None, 0x40),
+            (".text", "/tmp/fw/deps/libcore-0123.rlib", Some("core-0123.core.a1b2-cgu.0.rcgu.o"), 0x64),
+            (".text", "*fill*", None, 0x4),
+            (".text", "/usr/lib/libc.a", Some("memcpy.o"), 0xfc),
+            (".rodata.a_rather_long_output_section_name", "/tmp/fw/main.o", None, 0x10),
+            (".bss", "/tmp/fw/main.o", None, 0x20),
+        ]);
+    }
+
+    #[test]
+    fn lld_map() {
+        let header = LLD_MAP.lines().position(is_lld_header).unwrap();
+        let (output_sections, sections) = lld_sections(LLD_MAP, header);
+        assert_eq!(output_sections.get(".text"), Some(&0x1a8));
+        assert_eq!(output_sections.get(".bss"), Some(&0x20));
+        assert!(!output_sections.contains_key("end = ."));
+        assert_eq!(entries(&sections), vec![
+            (".text", "/tmp/fw/main.o", None, 0x40),
+            (".text", "/tmp/fw/deps/libcore-0123.rlib", Some("core-0123.core.a1b2-cgu.0.rcgu.o"), 0x64),
+            (".text", "<internal>", None, 0x4),
+            (".text", "/usr/lib/libc.a", Some("memcpy.o"), 0xfc),
+            (".text", "<linker script>", None, 0x4),
+            (".bss", "/tmp/fw/main.o", None, 0x20),
+        ]);
+    }
+
+    #[test]
+    fn merged_object_split_by_symbols() {
+        let footprint = |text, bss| Footprint { text, rodata: 0, data: 0, bss };
+        let mut footprints = MapFootprints {
+            path: PathBuf::from("fw.map"),
+            components: HashMap::from([("app".to_string(), footprint(100, 40)), ("native+lib".to_string(), footprint(8, 0))]),
+            unattributed: vec![],
+            total: footprint(108, 40),
+            merged_into: Some("app".into()),
+        };
+        let symbols = HashMap::from([("app".to_string(), footprint(50, 8)),
+                                     ("dep".to_string(), footprint(30, 48)),
+                                     ("other".to_string(), footprint(10, 16)),
+                                     ("native+lib".to_string(), footprint(1, 0))]);
+        footprints.split_merged(&symbols);
+        // the dependencies' shares come out of the merged object, scaled down where they'd be more than is in it
+        assert_eq!(footprints.components["dep"], footprint(30, 30));
+        assert_eq!(footprints.components["other"], footprint(10, 10));
+        assert_eq!(footprints.components["app"], footprint(60, 0));
+        assert_eq!(footprints.components["native+lib"], footprint(8, 0));
+    }
+}
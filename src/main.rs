@@ -1,79 +1,428 @@
+mod advisory;
 mod bloat;
 mod component;
 mod cliarg;
+mod dwarf;
+mod elf;
+mod embedded;
+mod error;
+mod firmware;
+mod generated;
+mod invocation;
+mod laze;
+mod linker;
+mod linkmap;
+mod native;
+mod ninja;
+mod outdated;
+mod registry;
 mod sbom;
+mod size;
+mod target;
+mod toolchain;
+mod vendored;
 
 use crate::{
-        bloat::{BloatOutput}, 
-        cliarg::{Args}
+        advisory::{AdvisoryDatabase},
+        bloat::{BloatData, BloatOutput},
+        cliarg::{Args},
+        elf::{ElfAnalyzer},
+        embedded::{EmbeddedFile},
+        error::{Error},
+        invocation::{BuildOptions, CargoInvocation},
+        laze::{LazeProject},
+        linker::{LinkerScript},
+        linkmap::{LinkerMap},
+        native::{NativeLibrary},
+        ninja::{NinjaFile},
+        outdated::{OutdatedReport},
+        registry::{RegistryIndex},
+        size::{SizeReport},
+        toolchain::{SHIM_CRATE, Toolchain},
 };
 
-use cargo_lock::{Lockfile, Error as LockError};
-use cargo_metadata::{Error as MetadataError, Metadata, MetadataCommand};
+use cargo_lock::{Lockfile};
+use cargo_metadata::{CargoOpt, Metadata, MetadataCommand};
 use clap::{Parser};
 
 use crate::sbom::{SBOM, BomFormat};
 
-use std::path::{Path};
+use std::{
+        collections::{HashMap, hash_map::Entry},
+        path::{Path, PathBuf},
+        process::{ExitCode},
+};
 
 
 
 
 
-fn generate_cargo_metadata(root_path: &Path, manifest_path: &Path) -> Result<Metadata, MetadataError> {
+// same target/features/-Z flags/environment as the build so the resolve graph matches it
+fn generate_cargo_metadata(root_path: &Path, manifest_path: &Path, cargo_invocation: &CargoInvocation, build_options: &BuildOptions) -> Result<Metadata, Error> {
         let mut metadata_command = MetadataCommand::default();
                 metadata_command.current_dir(root_path);
                 metadata_command.manifest_path(manifest_path);
-        // more for features/otheroptions in the future
-        metadata_command.exec()
+
+        if !build_options.features.is_empty() {
+                metadata_command.features(CargoOpt::SomeFeatures(build_options.features.clone()));
+        }
+        if build_options.all_features { metadata_command.features(CargoOpt::AllFeatures); }
+        if build_options.no_default_features { metadata_command.features(CargoOpt::NoDefaultFeatures); }
+
+        let mut other_options: Vec<String> = vec![];
+        if let Some(target) = &build_options.target {
+                other_options.push("--filter-platform".into());
+                other_options.push(target.clone());
+        }
+        for flag in build_options.unstable_flags.iter() {
+                other_options.push(format!("-Z{}", flag));
+        }
+        metadata_command.other_options(other_options);
+
+        for (name, value) in cargo_invocation.env.iter() {
+                metadata_command.env(name, value);
+        }
+        if let Some(toolchain) = &build_options.toolchain {
+                metadata_command.env("RUSTUP_TOOLCHAIN", toolchain);
+        }
+        // cargo metadata runs in the project root and might not see the build's config, so the flags are passed on as they were
+        if !build_options.rustflags.is_empty() {
+                metadata_command.env("CARGO_ENCODED_RUSTFLAGS", build_options.rustflags.join("\x1f"));
+        }
+
+        metadata_command.exec().map_err(|e| Error::CargoMetadata { manifest_path: manifest_path.into(), source: e })
+}
+
+fn generate_carg_lock_data(lock_path: &Path) -> Result<Lockfile, Error> {
+        Lockfile::load(lock_path).map_err(|e| Error::Lockfile { path: lock_path.into(), source: e })
+}
+
+// laze puts all apps for all builders into one ninja file, relative to the project root
+// builders: none -> there has to be exactly one build, "all" -> every builder in the file
+fn find_cargo_invocations(root_path: &Path, app: Option<&str>, builders: &[String]) -> Result<Vec<CargoInvocation>, Error> {
+        let ninja_path = Path::new("build/build-local.ninja");
+        let ninja_file = NinjaFile::load(ninja_path, root_path)?;
+
+        let invocations = CargoInvocation::find_in_ninja(&ninja_file);
+        if invocations.is_empty() {
+                return Err(Error::BuildFile { path: root_path.join(ninja_path), reason: "no cargo build statement found".into() });
+        }
+
+        let requested_builders: Vec<Option<&str>> = match builders {
+                [] => vec![None],
+                [all] if all == "all" => {
+                        let mut all_builders: Vec<Option<&str>> = vec![];
+                        for invocation in invocations.iter().filter(|invocation| invocation.matches(app, None)) {
+                                if !all_builders.contains(&invocation.builder.as_deref()) { all_builders.push(invocation.builder.as_deref()); }
+                        }
+                        all_builders
+                },
+                builders => builders.iter().map(|builder| Some(builder.as_str())).collect(),
+        };
+
+        let available: Vec<String> = invocations.iter().map(|invocation| invocation.app_and_builder()).collect();
+        let mut selected: Vec<CargoInvocation> = vec![];
+
+        for builder in requested_builders {
+                let mut matching: Vec<&CargoInvocation> = invocations
+                                                            .iter()
+                                                            .filter(|invocation| invocation.matches(app, builder))
+                                                            .collect();
+                match matching.len() {
+                        1 => selected.push(matching.remove(0).clone()),
+                        0 => return Err(Error::BuildSelection { reason: "no cargo build for the given --app/--builder".into(), available }),
+                        _ => return Err(Error::BuildSelection { reason: "several cargo builds found, select one with --app/--builder".into(), available }),
+                }
+        }
+
+        Ok(selected)
 }
 
-fn generate_carg_lock_data(lock_path: &Path) -> Result<Lockfile, LockError> {
-        Lockfile::load(lock_path)
+// plain cargo mode: run from the manifest's directory with the given arguments/environment
+fn plain_cargo_invocation(cli_args: &Args) -> Result<CargoInvocation, Error> {
+        // there is only the one build given after "--", nothing to select or merge
+        let laze_options: Vec<&str> = [(cli_args.app.is_some(), "--app"), (!cli_args.builders.is_empty(), "--builder"), (cli_args.aggregate, "--aggregate")]
+                                        .into_iter()
+                                        .filter_map(|(given, option)| given.then_some(option))
+                                        .collect();
+        if !laze_options.is_empty() {
+                return Err(Error::InvalidArgument { reason: format!("{} only for laze builds, not with --plain-cargo", laze_options.join(", ")) });
+        }
+
+        let mut env: Vec<(String, String)> = vec![];
+        for assignment in cli_args.cargo_env.iter() {
+                match assignment.split_once('=') {
+                        Some((name, value)) if !name.is_empty() => env.push((name.into(), value.into())),
+                        _ => return Err(Error::InvalidArgument { reason: format!("expected KEY=VALUE for --cargo-env, got {}", assignment) }),
+                }
+        }
+
+        let working_dir = cli_args.project_manifest_path.parent().map(|dir| dir.to_path_buf());
+
+        Ok(CargoInvocation::from_build_args(&cli_args.cargo_args, env, working_dir))
 }
 
+// the firmware that was already built if it can be found and read, otherwise cargo bloat (builds again);
+// also the ELF, if there is one, for the root component
+fn generate_bloat_data(cli_args: &Args, cargo_invocation: &CargoInvocation, metadata: &Metadata) -> Result<(BloatData, Option<PathBuf>), Error> {
+        let deps_dir = cargo_invocation.artifact_dir(&cli_args.project_root_path, metadata.target_directory.as_std_path()).join("deps");
+        let deps_dir = Some(deps_dir.as_path()).filter(|dir| dir.is_dir());
 
-fn main() {
+        let firmware_paths = cargo_invocation.firmware_paths(&cli_args.project_root_path, metadata.target_directory.as_std_path());
+        let (mut bloat_data, elf_path) = match (cli_args.cargo_bloat, &cli_args.elf_path) {
+                // cargo bloat builds it where cargo puts it
+                (true, _) => (BloatOutput::generate(&cli_args.project_root_path, cargo_invocation)?, firmware_paths.into_iter().find(|path| path.is_file())),
+                (false, Some(elf_path)) => (ElfAnalyzer::analyze(elf_path, metadata, deps_dir)?, Some(elf_path.clone())),
+                (false, None) => {
+                        let mut found: Option<(BloatData, Option<PathBuf>)> = None;
+                        for elf_path in firmware_paths
+                                            .iter()
+                                            .filter(|path| path.is_file()) {
+                                match ElfAnalyzer::analyze(elf_path, metadata, deps_dir) {
+                                        Ok(bloat_data) => {
+                                                println!("Analyzed firmware {}", elf_path.display());
+                                                found = Some((bloat_data, Some(elf_path.clone())));
+                                                break;
+                                        }
+                                        Err(e) => println!("{}", e),
+                                }
+                        }
+                        match found {
+                                Some(found) => found,
+                                None => {
+                                        println!("No usable firmware ELF found, falling back to cargo bloat");
+                                        (BloatOutput::generate(&cli_args.project_root_path, cargo_invocation)?, firmware_paths.iter().find(|path| path.is_file()).cloned())
+                                }
+                        }
+                }
+        };
+
+        bloat_data.match_packages(metadata);
+        Ok((bloat_data, elf_path))
+}
+
+// explicitly given paths have to work, default locations are optional
+fn open_local_database<T>(given_path: &Option<PathBuf>, default_path: Option<PathBuf>, open: fn(&Path) -> Option<T>, name: &'static str) -> Result<Option<T>, Error> {
+        match given_path {
+                Some(path) => match open(path) {
+                        Some(database) => Ok(Some(database)),
+                        None => Err(Error::LocalDatabase { name, path: path.clone() }),
+                },
+                None => {
+                        let database = default_path.as_deref().and_then(open);
+                        if database.is_none() { println!("No {} found at the default location, skipping", name); }
+                        Ok(database)
+                }
+        }
+}
+
+
+fn main() -> ExitCode {
 
         let cli_args = Args::parse();
 
-        if !(cli_args.project_root_path.exists()) { panic!("Cannot find project root path:\n{:?}", cli_args.project_root_path); }
+        match run(&cli_args) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                        eprintln!("Error: {}", e);
+                        ExitCode::from(e.exit_code())
+                }
+        }
+}
+
+fn run(cli_args: &Args) -> Result<(), Error> {
+
+        if !(cli_args.project_root_path.exists()) {
+                return Err(Error::InvalidArgument { reason: format!("Cannot find project root path {}", cli_args.project_root_path.display()) });
+        }
         
         // TODO: handle stuff that might have to be handled first by CLI arguments
                 // e.g. setting up logging; or "environment" for/if SBOMs to be created
 
-        // just one for now, potentially for different devices in the future
-        let mut sboms = SBOM::new(BomFormat::Raw);
+        let cargo_invocations = match cli_args.plain_cargo {
+                true => vec![plain_cargo_invocation(cli_args)?],
+                false => find_cargo_invocations(&cli_args.project_root_path, cli_args.app.as_deref(), &cli_args.builders)?,
+        };
 
+        let lock_data = generate_carg_lock_data(&cli_args.project_lock_path)?;
 
-        let bloat_data = BloatOutput::generate(&cli_args.project_root_path);
-        
-        // will need error handling in case metadata fails -> manual data gathering then?
-        let metadata = match generate_cargo_metadata(&cli_args.project_root_path, &cli_args.project_manifest_path) { 
-                Ok(metadata) => metadata,
-                Err(e) => panic!("Error generating cargo metadata:\n{e:?}"),
-        };
+        let registry_index = open_local_database(&cli_args.registry_index_path, RegistryIndex::default_path(), RegistryIndex::open, "registry index")?;
+        let advisories = open_local_database(&cli_args.advisory_db_path, AdvisoryDatabase::default_path(), AdvisoryDatabase::open, "RustSec advisory database")?;
+        if cli_args.outdated_report && registry_index.is_none() {
+                return Err(Error::InvalidArgument { reason: "the outdated report needs a registry index (--registry-index)".into() });
+        }
+
+        if cli_args.elf_path.is_some() && cargo_invocations.len() > 1 {
+                return Err(Error::InvalidArgument { reason: "--elf only works with a single build, select one with --app/--builder".into() });
+        }
+        if cli_args.map_path.is_some() && cargo_invocations.len() > 1 {
+                return Err(Error::InvalidArgument { reason: "--map only works with a single build, select one with --app/--builder".into() });
+        }
 
-        let lock_data = match generate_carg_lock_data(&cli_args.project_lock_path) {
-                Ok(lock_data) => lock_data,
-                Err(e)=> panic!("Error loading Cargo.lock data:\n{e:?}"),
+        // cargo metadata only depends on target/features/rustflags etc., not on the board itself
+        let mut metadata_cache: HashMap<BuildOptions, Metadata> = HashMap::new();
+        let mut board_sboms: Vec<SBOM> = vec![];
+        // builders' contexts, read once for all builds
+        let laze_project = match cli_args.plain_cargo {
+                true => LazeProject::default(),
+                false => LazeProject::read(&cli_args.project_root_path),
         };
 
-        // stripping for: executable and everything build related
+        for cargo_invocation in cargo_invocations.iter() {
 
-        let stripped_metadata: Metadata = match cli_args.bloat_filter {
-                true => bloat_data.filter_cargo_metadata(metadata),
-                false => metadata
-        };
+                println!("Generating SBOM for {}", cargo_invocation.app_and_builder());
+
+                // one SBOM per board, named after the builder if there are several
+                let output_name = match (cargo_invocations.len() > 1, &cargo_invocation.builder) {
+                        (true, Some(builder)) => format!("{}-{}", cli_args.output_name, builder),
+                        _ => cli_args.output_name.clone(),
+                };
+
+                let mut sboms = SBOM::new(BomFormat::Raw);
+                sboms.bom_metadata.app = cargo_invocation.app.clone();
+                sboms.bom_metadata.builder = cargo_invocation.builder.clone();
+
+                // manual data gathering in case metadata fails?
+                let build_options = cargo_invocation.build_options(linker::rustflags(&cli_args.project_root_path, cargo_invocation));
+                let metadata = match metadata_cache.entry(build_options.clone()) {
+                        Entry::Occupied(entry) => entry.get().clone(),
+                        Entry::Vacant(entry) => {
+                                let metadata = generate_cargo_metadata(&cli_args.project_root_path, &cli_args.project_manifest_path, cargo_invocation, entry.key())?;
+                                entry.insert(metadata).clone()
+                        }
+                };
+
+                let target_info = target::target_info(&cli_args.project_root_path, cargo_invocation, &laze_project);
+                sboms.bom_metadata.target = Some(target_info.clone());
+
+                let (mut bloat_data, elf_path) = generate_bloat_data(cli_args, cargo_invocation, &metadata)?;
+                let firmware = elf_path.as_ref().and_then(|elf_path| firmware::firmware(elf_path, &target_info, &metadata));
+
+                // -sys crates often have no code of their own in the firmware, their libraries do
+                let build_dir = cargo_invocation.artifact_dir(&cli_args.project_root_path, metadata.target_directory.as_std_path()).join("build");
+                let build_script_runs = native::build_script_runs(&build_dir, &metadata, &bloat_data);
+                bloat_data.add_linked_packages(build_script_runs.iter().filter(|run| run.links_native()).map(|run| run.package_id.clone()));
+                let linker_scripts = linker::linker_scripts(&cli_args.project_root_path, cargo_invocation, &build_script_runs, &metadata);
+                let compiled_files = embedded::compiled_files(&build_dir.with_file_name("deps"), &metadata);
+                let embedded_files = embedded::embedded_files(&compiled_files, &build_dir, &metadata, &bloat_data);
+                let generated_code = generated::generated_code(&compiled_files, &build_dir, &build_script_runs, &metadata, &bloat_data);
+                // a library in any of these was compiled, not shipped with the sources
+                let output_dirs: Vec<PathBuf> = [Some(build_dir.clone()),
+                                                 Some(metadata.target_directory.clone().into_std_path_buf()),
+                                                 (!cli_args.plain_cargo).then(|| cli_args.project_root_path.join("build"))]
+                                                    .into_iter()
+                                                    .flatten()
+                                                    .collect();
+                let mut native_libraries: Vec<NativeLibrary> = build_script_runs.iter().flat_map(|run| run.native_libraries(&output_dirs)).collect();
+                native_libraries.extend(native::link_arg_libraries(&build_options.rustflags, &build_script_runs, &metadata, &output_dirs));
+
+                // exact per object file, including C code and blobs
+                let linker_map = match (&cli_args.map_path, &elf_path) {
+                        (Some(map_path), _) => Some(LinkerMap::read(map_path)?),
+                        (None, Some(elf_path)) => linkmap::find_linker_map(&cli_args.project_root_path, cargo_invocation, &build_script_runs, &metadata, elf_path)
+                                                        .and_then(|map_path| match LinkerMap::read(&map_path) {
+                                                                Ok(linker_map) if linker_map.matches_elf(elf_path) => Some(linker_map),
+                                                                Ok(_) => {
+                                                                        println!("Linker map {} is from another link than {}, ignoring it", map_path.display(), elf_path.display());
+                                                                        None
+                                                                }
+                                                                Err(e) => {
+                                                                        println!("{}", e);
+                                                                        None
+                                                                }
+                                                        }),
+                        (None, None) => None,
+                };
+
+                // stripping for: executable and everything build related
+
+                let stripped_metadata: Metadata = match cli_args.bloat_filter {
+                        true => bloat_data.filter_cargo_metadata(metadata)?,
+                        false => metadata
+                };
+
+                // extract information from cargo metadata
+                sboms.convert_cargo_metadata_packages_to_components(&stripped_metadata, &lock_data)?;
+
+                sboms.add_code_sizes(&stripped_metadata, &bloat_data);
+                sboms.add_footprints(&stripped_metadata, &bloat_data);
+
+                sboms.flag_yanked_and_unmaintained(&stripped_metadata, registry_index.as_ref(), advisories.as_ref());
+
+                if let (true, Some(registry_index)) = (cli_args.outdated_report, &registry_index) {
+                        let outdated_report = OutdatedReport::generate(&stripped_metadata, &bloat_data, registry_index);
+                        outdated_report.print_table();
+                        outdated_report.write_to_file(&output_name)?;
+                }
+
+                // root of the dependency tree
+                if let Some(firmware) = &firmware {
+                        let components = firmware.to_components();
+                        if !components.is_empty() { sboms.bom_metadata.component = Some(firmware.id()); }
+                        sboms.add_components(components);
+                }
+                // hardware it was built for
+                if let Some(device) = target_info.to_device_component() { sboms.add_components(vec![device]); }
+
+                // standard library crates are in the firmware but not in cargo metadata
+                let mut std_ids: HashMap<String, String> = HashMap::new();
+                match Toolchain::detect(&cli_args.project_root_path, cargo_invocation) {
+                        Some(toolchain) => {
+                                let mut std_components = toolchain.std_components(&build_options, |name| bloat_data.contains_crate(name));
+                                let std_crates: Vec<String> = std_components.iter().map(|component| component.name.replace("-", "_")).collect();
+                                std_ids = std_crates.iter().cloned().zip(std_components.iter().map(|component| component.id.clone())).collect();
+                                for (component, name) in std_components.iter_mut().zip(std_crates.iter()) {
+                                        if let Some(footprint) = bloat_data.crate_footprint(name) {
+                                                component.properties.extend(footprint.properties(bloat_data.is_text_only()));
+                                        }
+                                }
+                                bloat_data.remove_unmatched(&std_crates);
+                                bloat_data.remove_unmatched(&[SHIM_CRATE.into()]);
+                                sboms.add_components(std_components);
+                                sboms.bom_metadata.tools = toolchain.tools();
+                        }
+                        None => println!("Cannot run rustc for the build's toolchain, no standard library components"),
+                }
+
+                sboms.add_components(native_libraries.iter().map(NativeLibrary::to_component).collect());
+                for run in build_script_runs.iter() { sboms.add_components(vendored::vendored_libraries(run)); }
+                sboms.add_components(linker_scripts.iter().map(LinkerScript::to_component).collect());
+                sboms.add_components(embedded_files.iter().map(EmbeddedFile::to_component).collect());
+                for code in generated_code.iter() { sboms.add_components(code.to_components()); }
+
+                match &linker_map {
+                        Some(linker_map) => {
+                                println!("Attributing sizes by linker map {}", linker_map.path.display());
+                                let mut map_footprints = linker_map.footprints(elf_path.as_deref(), &stripped_metadata, &native_libraries, &std_ids);
+                                if !bloat_data.is_text_only() { map_footprints.split_merged(&bloat_data.component_footprints(&std_ids)); }
+                                sboms.set_map_footprints(&map_footprints);
+                                if cli_args.size_report {
+                                        let size_report = SizeReport::generate(&map_footprints, &sboms.components);
+                                        size_report.print_table();
+                                        size_report.write_to_file(&output_name)?;
+                                }
+                        }
+                        None if cli_args.size_report => println!("No linker map found for {}, no size report", cargo_invocation.app_and_builder()),
+                        None => {}
+                }
+
+                bloat_data.print_unmatched();
+                sboms.add_unmatched_crates(bloat_data.unmatched_crates());
+                sboms.add_composition(&bloat_data);
 
-        // extract information from cargo metadata
-        sboms.convert_cargo_metadata_packages_to_components(&stripped_metadata, &lock_data);
+                // TODO:
+                        // complete missing info
+                        // non-Metadata/-Rust stuff
 
-        // TODO:
-                // complete missing info
-                // non-Metadata/-Rust stuff
+                sboms.write_to_file(&output_name)?;
+                if cli_args.aggregate { board_sboms.push(sboms); }
+        }
 
-        sboms.write_to_file(&cli_args.output_name);
+        if cli_args.aggregate {
+                let family_sbom = SBOM::aggregate(BomFormat::Raw, &board_sboms);
+                family_sbom.write_to_file(&format!("{}-family", cli_args.output_name))?;
+        }
 
+        Ok(())
 }
 
@@ -0,0 +1,438 @@
+use crate::{
+    bloat::{BloatData},
+    component::{Component, ComponentType, Property},
+    dwarf::{SourceOwner, normalize, package_roots, source_owner},
+    linker::{link_args},
+};
+
+use cargo_metadata::{DependencyKind, Metadata, Package};
+use semver::{Version};
+use sha2::{Digest, Sha256, Sha512};
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime},
+};
+
+const PROPRIETARY_LICENSE: &str = "LicenseRef-Proprietary";
+
+// a library a build script told rustc to link (cargo:rustc-link-lib)
+pub struct NativeLibrary {
+    // package id of the crate whose build script it is
+    pub package_id: String,
+    pub package_name: String,
+    pub package_version: Version,
+    pub name: String,
+    // static, dylib, framework or empty (rustc's default)
+    pub kind: String,
+    // the library file in the link search paths, None for system libraries
+    pub path: Option<PathBuf>,
+    // not compiled in this build: where the blob comes from (in the crate or e.g. a vendor SDK)
+    pub prebuilt_from: Option<PathBuf>,
+    pub package_root: PathBuf,
+}
+
+impl NativeLibrary {
+
+    pub fn id(&self) -> String {
+        format!("native+{}@{}#{}", self.package_name, self.package_version, self.name)
+    }
+
+    // static archives get hashed and their objects listed, everything else is just named;
+    // prebuilt blobs are firmware/binary with SHA-512 and proprietary unless there's a license next to them
+    pub fn to_component(&self) -> Component {
+        let data = self.path.as_ref().and_then(|path| fs::read(path).ok());
+        let hash = data.map(|data| match self.prebuilt_from {
+            Some(_) => hex(&Sha512::digest(&data)),
+            None => hex(&Sha256::digest(&data)),
+        });
+        let mut component = Component::create_native_component(
+            self.id(),
+            &self.name,
+            self.package_id.clone(),
+            self.path.as_ref().and_then(|path| path.file_name()).map(|name| name.to_string_lossy().into_owned()),
+            hash);
+
+        if let Some(prebuilt_from) = &self.prebuilt_from {
+            component.component_type = ComponentType::Binary;
+            component.properties.push(Property::new("arielosbom:prebuilt_path", prebuilt_from.display()));
+            match license_near(prebuilt_from, &self.package_root) {
+                Some((license_file, Some(license))) => {
+                    component.licenses = Some(license);
+                    component.properties.push(Property::new("arielosbom:license_file", license_file.display()));
+                }
+                // a license, but not one we can name
+                Some((license_file, None)) => component.properties.push(Property::new("arielosbom:license_file", license_file.display())),
+                None => component.licenses = Some(PROPRIETARY_LICENSE.into()),
+            }
+        }
+
+        if !self.kind.is_empty() { component.properties.push(Property::new("arielosbom:link_kind", &self.kind)); }
+        if self.path.is_none() { component.properties.push(Property::new("arielosbom:system_library", true)); }
+        if let Some(objects) = self.path.as_ref().and_then(|path| archive_objects(path)) {
+            component.archive_property = Some(true);
+            if !objects.is_empty() { component.properties.push(Property::new("arielosbom:objects", objects.join(", "))); }
+        }
+        component
+    }
+}
+
+// latest run of the build script of a package in the firmware
+pub struct BuildScriptRun {
+    pub package_id: String,
+    pub package_name: String,
+    pub package_version: Version,
+    pub package_root: PathBuf,
+    // "links" key of the package (-sys crates)
+    pub links: Option<String>,
+    // rustc-link-arg only applies to the package's own targets, i.e. the firmware's package(s)
+    pub workspace_member: bool,
+    pub out_dir: PathBuf,
+    output: BuildScriptOutput,
+}
+
+// build script runs in <artifact dir>/build/<package>-<hash>/ with their output file; only packages in the
+// firmware and -sys crates they depend on (no Rust code of their own, so not in the symbols)
+pub fn build_script_runs(build_dir: &Path, metadata: &Metadata, bloat_data: &BloatData) -> Vec<BuildScriptRun> {
+    let Ok(entries) = fs::read_dir(build_dir) else { return vec![] };
+    let reachable = linked_closure(metadata, bloat_data);
+    let package_roots = package_roots(metadata);
+
+    // package id -> newest run (target dirs keep the runs of earlier builds with other features/versions)
+    let mut runs: HashMap<String, (SystemTime, PathBuf, BuildScriptOutput)> = HashMap::new();
+    for run_dir in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+        let output_path = run_dir.join("output");
+        let Ok(content) = fs::read_to_string(&output_path) else { continue };
+        let output = BuildScriptOutput::parse(&content);
+
+        let Some(dir_name) = run_dir.file_name().and_then(|name| name.to_str()) else { continue };
+        let Some((package_name, _)) = dir_name.rsplit_once('-') else { continue };
+        let candidates: Vec<&Package> = metadata.packages
+                                            .iter()
+                                            .filter(|package| package.name.as_str() == package_name && reachable.contains(&package.id.repr))
+                                            .collect();
+        let package = match candidates.as_slice() {
+            [] => continue,
+            [package] => *package,
+            // several versions: paths in the output (rerun-if-changed, include dirs) that are in one of them
+            _ => match output.paths.iter().find_map(|path| match source_owner(path, metadata, &package_roots) {
+                SourceOwner::Package(id) => candidates.iter().find(|package| package.id.repr == id).copied(),
+                _ => None,
+            }) {
+                Some(package) => package,
+                // only worth a word if the run has a say in linking, most scripts just probe the compiler etc.
+                None => {
+                    if output.links_native() {
+                        println!("Cannot tell which version of {} ran build script {}, skipping it", package_name, run_dir.display());
+                    }
+                    continue;
+                }
+            },
+        };
+
+        let modified = fs::metadata(&output_path).and_then(|metadata| metadata.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+        if runs.get(&package.id.repr).is_none_or(|(newest, _, _)| modified > *newest) {
+            runs.insert(package.id.repr.clone(), (modified, run_dir, output));
+        }
+    }
+
+    metadata.packages
+        .iter()
+        .filter_map(|package| {
+            let (_, run_dir, output) = runs.remove(&package.id.repr)?;
+            Some(BuildScriptRun {
+                package_id: package.id.repr.clone(),
+                package_name: package.name.to_string(),
+                package_version: package.version.clone(),
+                package_root: package.manifest_path.parent()?.as_std_path().to_path_buf(),
+                links: package.links.clone(),
+                workspace_member: metadata.workspace_members.contains(&package.id),
+                // root-output has the OUT_DIR the script ran with
+                out_dir: fs::read_to_string(run_dir.join("root-output"))
+                            .map(|out_dir| PathBuf::from(out_dir.trim()))
+                            .unwrap_or_else(|_| run_dir.join("out")),
+                output,
+            })
+        })
+        .collect()
+}
+
+impl BuildScriptRun {
+
+    pub fn native_libraries(&self, output_dirs: &[PathBuf]) -> Vec<NativeLibrary> {
+        let library = |name: &str, kind: &str, path: Option<PathBuf>| NativeLibrary {
+            package_id: self.package_id.clone(),
+            package_name: self.package_name.clone(),
+            package_version: self.package_version.clone(),
+            name: name.into(),
+            kind: kind.into(),
+            prebuilt_from: path.as_ref().and_then(|path| prebuilt_origin(path, output_dirs, &self.package_root)),
+            path,
+            package_root: self.package_root.clone(),
+        };
+
+        let mut seen: HashSet<&String> = HashSet::new();
+        self.output.link_libs
+            .iter()
+            .filter(|(_, name)| seen.insert(name))
+            .map(|(kind, name)| library(name, kind, library_file(&self.output.search_paths, name, kind)))
+            .collect()
+    }
+
+    // has a say in linking (-sys crates, linker scripts, ...)
+    pub fn links_native(&self) -> bool {
+        self.output.links_native()
+    }
+
+    // rerun-if-changed paths (relative to the package) and rerun-if-env-changed variables: what the script reads
+    pub fn inputs(&self) -> (&[PathBuf], &[String]) {
+        (&self.output.rerun_if_changed, &self.output.rerun_if_env_changed)
+    }
+
+    pub fn link_args(&self) -> &[String] {
+        match self.workspace_member {
+            true => &self.output.link_args,
+            false => &[],
+        }
+    }
+
+    pub fn search_paths(&self) -> &[PathBuf] {
+        &self.output.search_paths
+    }
+}
+
+// objects/archives given to the linker directly: -C link-arg in the rustflags (e.g. laze's) and the firmware package's
+// rustc-link-arg; relative ones are from the working dir (the workspace root) or the crate whose build script passed them.
+// Owned by the package they're in, or the OUT_DIR's package, otherwise by the package the firmware is built from
+pub fn link_arg_libraries(rustflags: &[String], runs: &[BuildScriptRun], metadata: &Metadata, output_dirs: &[PathBuf]) -> Vec<NativeLibrary> {
+    let package_roots = package_roots(metadata);
+    let workspace_members = metadata.workspace_packages();
+    let firmware_package = metadata.root_package().or_else(|| (workspace_members.len() == 1).then(|| workspace_members[0]));
+
+    let mut libraries: Vec<NativeLibrary> = vec![];
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+    for argument in link_args(rustflags, runs).iter().filter(|argument| Path::new(argument).extension().is_some_and(|extension| extension == "a" || extension == "o" || extension == "lib")) {
+        let Some(path) = std::iter::once(metadata.workspace_root.as_std_path())
+                            .chain(runs.iter().filter(|run| !run.link_args().is_empty()).map(|run| run.package_root.as_path()))
+                            .map(|dir| dir.join(argument))
+                            .find(|path| path.is_file()) else { continue };
+        let path = normalize(&path);
+        if !seen.insert(path.clone()) { continue; }
+        let Some(name) = path.file_name().map(|name| name.to_string_lossy().into_owned()) else { continue };
+
+        let owner = match runs.iter().find(|run| path.starts_with(&run.out_dir)) {
+            Some(run) => Some(run.package_id.clone()),
+            None => match source_owner(&path, metadata, &package_roots) {
+                SourceOwner::Package(id) => Some(id),
+                _ => firmware_package.map(|package| package.id.repr.clone()),
+            },
+        };
+        let Some(package) = owner.and_then(|id| metadata.packages.iter().find(|package| package.id.repr == id)) else {
+            println!("Cannot tell which package links {}, skipping it", path.display());
+            continue;
+        };
+        let Some(package_root) = package.manifest_path.parent().map(|root| root.as_std_path().to_path_buf()) else { continue };
+
+        libraries.push(NativeLibrary {
+            package_id: package.id.repr.clone(),
+            package_name: package.name.to_string(),
+            package_version: package.version.clone(),
+            name,
+            kind: "link-arg".into(),
+            prebuilt_from: prebuilt_origin(&path, output_dirs, &package_root),
+            path: Some(path),
+            package_root,
+        });
+    }
+    libraries
+}
+
+// compiled in this build = in one of the build's output dirs (cargo's build and target dirs, laze's build dir)
+// and not just copied there from the crate
+fn prebuilt_origin(path: &Path, output_dirs: &[PathBuf], package_root: &Path) -> Option<PathBuf> {
+    if !output_dirs.iter().any(|dir| path.starts_with(dir)) { return Some(path.to_path_buf()); }
+    find_file(package_root, path.file_name()?, output_dirs, 8)
+}
+
+// sources only: output dirs inside the package (e.g. a workspace member's target dir) hold build results
+fn find_file(dir: &Path, file_name: &std::ffi::OsStr, output_dirs: &[PathBuf], depth: usize) -> Option<PathBuf> {
+    let entries: Vec<PathBuf> = fs::read_dir(dir).ok()?.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect();
+    if let Some(found) = entries.iter().find(|path| path.is_file() && path.file_name() == Some(file_name)) {
+        return Some(found.clone());
+    }
+    if depth == 0 { return None; }
+    entries
+        .iter()
+        .filter(|path| path.is_dir() && !output_dirs.iter().any(|output_dir| path.starts_with(output_dir)))
+        .find_map(|path| find_file(path, file_name, output_dirs, depth - 1))
+}
+
+// LICENSE*/COPYING* file in the blob's directory or the vendor directory right above it (not the crate root,
+// the crate's license isn't the blob's), with its SPDX-License-Identifier if it has one
+pub fn license_near(path: &Path, package_root: &Path) -> Option<(PathBuf, Option<String>)> {
+    let blob_dir = path.parent()?;
+    let vendor_dir = blob_dir.parent().filter(|dir| *dir != package_root && (dir.starts_with(package_root) || !path.starts_with(package_root)));
+    for dir in std::iter::once(blob_dir).chain(vendor_dir) {
+        let Ok(entries) = fs::read_dir(dir) else { continue };
+        let mut license_files: Vec<PathBuf> = entries
+                                                .filter_map(|entry| entry.ok())
+                                                .map(|entry| entry.path())
+                                                .filter(|path| path.is_file())
+                                                .filter(|path| path.file_name()
+                                                                .map(|name| name.to_string_lossy().to_uppercase())
+                                                                .is_some_and(|name| name.starts_with("LICENSE") || name.starts_with("LICENCE") || name.starts_with("COPYING")))
+                                                .collect();
+        license_files.sort();
+        let Some(license_file) = license_files.first() else { continue };
+        let identifier = license_files
+                            .iter()
+                            .filter_map(|file| fs::read_to_string(file).ok())
+                            .find_map(|content| content
+                                                .lines()
+                                                .find_map(|line| line.split_once("SPDX-License-Identifier:"))
+                                                .map(|(_, identifier)| identifier.trim().trim_end_matches("*/").trim().to_string()));
+        return Some((license_file.clone(), identifier));
+    }
+    None
+}
+
+// linked packages and what they (transitively) depend on for normal, non-build code
+fn linked_closure(metadata: &Metadata, bloat_data: &BloatData) -> HashSet<String> {
+    let mut reachable: HashSet<String> = metadata.packages
+                                            .iter()
+                                            .filter(|package| bloat_data.is_linked(package))
+                                            .map(|package| package.id.repr.clone())
+                                            .collect();
+    let Some(resolve) = metadata.resolve.as_ref() else { return reachable };
+    let nodes: HashMap<&String, &cargo_metadata::Node> = resolve.nodes.iter().map(|node| (&node.id.repr, node)).collect();
+
+    let mut queue: Vec<String> = reachable.iter().cloned().collect();
+    while let Some(id) = queue.pop() {
+        let Some(node) = nodes.get(&id) else { continue };
+        for dep in node.deps.iter().filter(|dep| dep.dep_kinds.iter().any(|info| info.kind == DependencyKind::Normal)) {
+            if reachable.insert(dep.pkg.repr.clone()) { queue.push(dep.pkg.repr.clone()); }
+        }
+    }
+    reachable
+}
+
+// the directives cargo passes on to rustc for linking
+#[derive(Default)]
+struct BuildScriptOutput {
+    // (kind, name)
+    link_libs: Vec<(String, String)>,
+    search_paths: Vec<PathBuf>,
+    // rustc-link-arg* flags (for the bins, tests, ... of the package)
+    link_args: Vec<String>,
+    rerun_if_changed: Vec<PathBuf>,
+    rerun_if_env_changed: Vec<String>,
+    // absolute paths mentioned anywhere, to tell which package version the run belongs to
+    paths: Vec<PathBuf>,
+}
+
+impl BuildScriptOutput {
+
+    // libraries, link args or search paths (-sys crates, linker scripts, ...)
+    fn links_native(&self) -> bool {
+        !self.link_libs.is_empty() || !self.link_args.is_empty() || !self.search_paths.is_empty()
+    }
+
+    // cargo:KEY=VALUE (old) and cargo::KEY=VALUE (since 1.77)
+    fn parse(content: &str) -> BuildScriptOutput {
+        let mut output = BuildScriptOutput::default();
+        for line in content.lines() {
+            let Some(directive) = line.strip_prefix("cargo::").or_else(|| line.strip_prefix("cargo:")) else { continue };
+            let Some((key, value)) = directive.split_once('=') else { continue };
+            match key {
+                "rustc-link-lib" => output.add_link_lib(value),
+                "rustc-link-search" => output.add_search_path(value),
+                "rerun-if-changed" => output.rerun_if_changed.push(PathBuf::from(value)),
+                "rerun-if-env-changed" => output.rerun_if_env_changed.push(value.into()),
+                // -l and -L only, e.g. "-l static=foo -L native=/path"
+                "rustc-flags" => {
+                    let mut words = value.split_whitespace();
+                    while let Some(word) = words.next() {
+                        let (flag, argument) = match word.len() > 2 {
+                            true => word.split_at(2),
+                            false => (word, words.next().unwrap_or_default()),
+                        };
+                        match flag {
+                            "-l" => output.add_link_lib(argument),
+                            "-L" => output.add_search_path(argument),
+                            _ => {}
+                        }
+                    }
+                }
+                // rustc-link-arg-bin=BIN=FLAG, the other rustc-link-arg* are just the flag
+                _ if key.starts_with("rustc-link-arg") => {
+                    let argument = match key == "rustc-link-arg-bin" {
+                        true => value.split_once('=').map(|(_, argument)| argument).unwrap_or_default(),
+                        false => value,
+                    };
+                    output.link_args.push(argument.into());
+                }
+                _ => {}
+            }
+            output.paths.extend(value.split(['=', ' ', ',']).filter(|part| part.starts_with('/')).map(PathBuf::from));
+        }
+        output
+    }
+
+    // [KIND[:MODIFIERS]=]NAME[:RENAME]
+    fn add_link_lib(&mut self, value: &str) {
+        let (kind, name) = match value.split_once('=') {
+            Some((kind, name)) => (kind.split(':').next().unwrap_or_default(), name),
+            None => ("", value),
+        };
+        let name = name.split(':').next().unwrap_or_default();
+        if !name.is_empty() { self.link_libs.push((kind.into(), name.into())); }
+    }
+
+    // [KIND=]PATH
+    fn add_search_path(&mut self, value: &str) {
+        let path = match value.split_once('=') {
+            Some(("native" | "crate" | "dependency" | "framework" | "all", path)) => path,
+            _ => value,
+        };
+        self.search_paths.push(PathBuf::from(path));
+    }
+}
+
+// the file the linker would pick in the build script's search paths
+fn library_file(search_paths: &[PathBuf], name: &str, kind: &str) -> Option<PathBuf> {
+    let file_names = match kind {
+        "static" => vec![format!("lib{}.a", name), format!("{}.lib", name)],
+        _ => vec![format!("lib{}.so", name), format!("lib{}.dylib", name), format!("{}.lib", name), format!("lib{}.a", name)],
+    };
+    search_paths
+        .iter()
+        .flat_map(|dir| file_names.iter().map(move |file_name| dir.join(file_name)))
+        .find(|path| path.is_file())
+}
+
+// member names of a static archive, without the hash prefix the cc crate puts in front ("f0389296f42960e9-adler32.o")
+pub fn archive_objects(path: &Path) -> Option<Vec<String>> {
+    let data = fs::read(path).ok()?;
+    let archive = object::read::archive::ArchiveFile::parse(&*data).ok()?;
+    let mut objects: Vec<String> = archive.members()
+                                    .filter_map(|member| member.ok())
+                                    .map(|member| object_name(&String::from_utf8_lossy(member.name())).to_string())
+                                    .collect();
+    objects.sort();
+    objects.dedup();
+    Some(objects)
+}
+
+// rustc bundles static libraries into the -sys crate's rlib with these names
+pub fn object_name(member: &str) -> &str {
+    match member.split_once('-') {
+        Some((prefix, rest)) if prefix.len() == 16 && prefix.chars().all(|character| character.is_ascii_hexdigit()) => rest,
+        _ => member,
+    }
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -0,0 +1,551 @@
+use std::{
+    collections::{HashMap},
+    fmt::{Formatter},
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+
+// parser for the ninja files laze generates (or any other ninja file really)
+// https://ninja-build.org/manual.html#ref_ninja_file
+// build statements get their bindings and rule variables ($command etc.) evaluated while parsing,
+// so the result doesn't need the scopes anymore
+
+#[derive(Debug)]
+pub enum NinjaError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Syntax {
+        path: PathBuf,
+        line: usize,
+        reason: String,
+    },
+}
+
+impl std::fmt::Display for NinjaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            NinjaError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            NinjaError::Syntax { path, line, reason } => write!(f, "{}:{}: {}", path.display(), line, reason),
+        }
+    }
+}
+
+// complete view of the statement, not everything is used (yet)
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct BuildEdge {
+    pub rule: String,
+    pub outputs: Vec<String>,
+    pub implicit_outputs: Vec<String>,
+    pub inputs: Vec<String>,
+    pub implicit_inputs: Vec<String>,
+    pub order_only_inputs: Vec<String>,
+    // build level bindings and the rule's variables, all evaluated for this edge
+    pub variables: HashMap<String, String>,
+}
+
+impl BuildEdge {
+
+    pub fn command(&self) -> Option<&str> {
+        self.variables.get("command").map(|command| command.as_str())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct NinjaFile {
+    pub builds: Vec<BuildEdge>,
+    pub defaults: Vec<String>,
+    // top level variables of the main file
+    pub variables: HashMap<String, String>,
+}
+
+impl NinjaFile {
+
+    // working_dir: where ninja would run, include/subninja paths are relative to it
+    pub fn load(path: &Path, working_dir: &Path) -> Result<NinjaFile, NinjaError> {
+        let mut parser = Parser {
+            working_dir: working_dir.to_path_buf(),
+            scopes: vec![Scope::default()],
+            ninja_file: NinjaFile::default(),
+        };
+        parser.parse_file(path)?;
+
+        parser.ninja_file.variables = parser.scopes.remove(0).variables;
+        Ok(parser.ninja_file)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Token {
+    Literal(String),
+    Variable(String),
+}
+
+// unevaluated string with $variable references
+#[derive(Clone, Debug, Default)]
+struct EvalString {
+    tokens: Vec<Token>,
+}
+
+impl EvalString {
+
+    fn evaluate(&self, lookup: &dyn Fn(&str) -> String) -> String {
+        let mut result = String::new();
+        for token in self.tokens.iter() {
+            match token {
+                Token::Literal(literal) => result.push_str(literal),
+                Token::Variable(name) => result.push_str(&lookup(name)),
+            }
+        }
+        result
+    }
+
+    fn push_literal(&mut self, character: char) {
+        match self.tokens.last_mut() {
+            Some(Token::Literal(literal)) => literal.push(character),
+            _ => self.tokens.push(Token::Literal(character.into())),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Rule {
+    bindings: HashMap<String, EvalString>,
+}
+
+#[derive(Debug, Default)]
+struct Scope {
+    variables: HashMap<String, String>,
+    rules: HashMap<String, Rule>,
+}
+
+struct Parser {
+    working_dir: PathBuf,
+    // subninja opens a child scope, include doesn't
+    scopes: Vec<Scope>,
+    ninja_file: NinjaFile,
+}
+
+// one logical line, continuations already joined
+struct Line {
+    number: usize,
+    indented: bool,
+    text: String,
+}
+
+enum Declaration {
+    Rule(String, Rule),
+    Build(usize, String),
+    Pool,
+}
+
+impl Parser {
+
+    fn parse_file(&mut self, path: &Path) -> Result<(), NinjaError> {
+
+        let full_path = self.working_dir.join(path);
+        let content = match fs::read_to_string(&full_path) {
+            Ok(content) => content,
+            Err(e) => return Err(NinjaError::Io { path: full_path, source: e }),
+        };
+
+        let syntax_error = |line: usize, reason: String| NinjaError::Syntax { path: full_path.clone(), line, reason };
+
+        let mut lines = logical_lines(&content).into_iter().peekable();
+
+        while let Some(line) = lines.next() {
+
+            if line.indented {
+                return Err(syntax_error(line.number, "unexpected indentation".into()));
+            }
+
+            let (keyword, rest) = match line.text.split_once([' ', '\t']) {
+                Some((keyword, rest)) => (keyword, rest.trim_start()),
+                None => (line.text.as_str(), ""),
+            };
+
+            let declaration = match keyword {
+                "rule" => Declaration::Rule(rest.trim().into(), Rule::default()),
+                "build" => Declaration::Build(line.number, rest.into()),
+                "pool" => Declaration::Pool,
+                "default" => {
+                    let (paths, _) = parse_paths(rest, false);
+                    for item in paths.iter() {
+                        if let PathItem::Path(path) = item {
+                            let evaluated = self.evaluate_in_scope(path);
+                            self.ninja_file.defaults.push(evaluated);
+                        }
+                    }
+                    continue;
+                }
+                "include" | "subninja" => {
+                    let included_path = self.evaluate_in_scope(&parse_value(rest));
+                    if keyword == "subninja" { self.scopes.push(Scope::default()); }
+                    self.parse_file(Path::new(&included_path))?;
+                    if keyword == "subninja" { self.scopes.pop(); }
+                    continue;
+                }
+                _ => {
+                    let Some((name, value)) = parse_binding(&line.text) else {
+                        return Err(syntax_error(line.number, format!("expected variable binding, found: {}", line.text)));
+                    };
+                    let evaluated = self.evaluate_in_scope(&value);
+                    self.current_scope().variables.insert(name, evaluated);
+                    continue;
+                }
+            };
+
+            // indented bindings belonging to the declaration
+            let mut bindings: Vec<(String, EvalString)> = vec![];
+            while let Some(binding_line) = lines.next_if(|next| next.indented) {
+                match parse_binding(&binding_line.text) {
+                    Some(binding) => bindings.push(binding),
+                    None => return Err(syntax_error(binding_line.number, format!("expected variable binding, found: {}", binding_line.text))),
+                }
+            }
+
+            match declaration {
+                Declaration::Rule(name, mut rule) => {
+                    rule.bindings.extend(bindings);
+                    self.current_scope().rules.insert(name, rule);
+                }
+                Declaration::Build(line_number, text) => {
+                    let edge = self.parse_build(&text, bindings).map_err(|reason| syntax_error(line_number, reason))?;
+                    self.ninja_file.builds.push(edge);
+                }
+                Declaration::Pool => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_build(&self, text: &str, bindings: Vec<(String, EvalString)>) -> Result<BuildEdge, String> {
+
+        let (outputs, rest) = parse_paths(text, true);
+        let Some(rest) = rest.strip_prefix(':') else {
+            return Err("expected ':' after build outputs".into());
+        };
+        let rest = rest.trim_start();
+        let (rule_name, rest) = rest.split_once([' ', '\t']).unwrap_or((rest, ""));
+        let (inputs, _) = parse_paths(rest, false);
+
+        // outputs: explicit | implicit
+        let mut explicit_outputs: Vec<String> = vec![];
+        let mut implicit_outputs: Vec<String> = vec![];
+        let mut after_separator = false;
+        for item in outputs.iter() {
+            match item {
+                PathItem::Separator(_) => after_separator = true,
+                PathItem::Path(path) if after_separator => implicit_outputs.push(self.evaluate_in_scope(path)),
+                PathItem::Path(path) => explicit_outputs.push(self.evaluate_in_scope(path)),
+            }
+        }
+        if explicit_outputs.is_empty() && implicit_outputs.is_empty() {
+            return Err("build statement without outputs".into());
+        }
+
+        // input groups in order: explicit | implicit || order-only |@ validations
+        let mut explicit_inputs: Vec<String> = vec![];
+        let mut implicit_inputs: Vec<String> = vec![];
+        let mut order_only_inputs: Vec<String> = vec![];
+        let mut current_group = 0;
+        for item in inputs.iter() {
+            match item {
+                PathItem::Separator(separator) => current_group = match separator.as_str() {
+                    "|" => 1,
+                    "||" => 2,
+                    _ => 3,
+                },
+                PathItem::Path(path) => {
+                    let evaluated = self.evaluate_in_scope(path);
+                    match current_group {
+                        0 => explicit_inputs.push(evaluated),
+                        1 => implicit_inputs.push(evaluated),
+                        2 => order_only_inputs.push(evaluated),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        let rule = match rule_name {
+            "phony" => Rule::default(),
+            name => match self.lookup_rule(name) {
+                Some(rule) => rule.clone(),
+                None => return Err(format!("unknown rule: {}", name)),
+            }
+        };
+
+        // build bindings: evaluated in file scope
+        let variables: HashMap<String, String> = bindings
+                                                    .iter()
+                                                    .map(|(name, value)| (name.clone(), self.evaluate_in_scope(value)))
+                                                    .collect();
+
+        // rule bindings: $in/$out, build bindings, rule bindings, file scope
+        let mut edge = BuildEdge {
+            rule: rule_name.into(),
+            outputs: explicit_outputs,
+            implicit_outputs,
+            inputs: explicit_inputs,
+            implicit_inputs,
+            order_only_inputs,
+            variables: HashMap::new(),
+        };
+        for name in rule.bindings.keys() {
+            if variables.contains_key(name) { continue; }
+            let evaluated = self.evaluate_rule_variable(name, &rule, &edge, &variables, 0);
+            edge.variables.insert(name.clone(), evaluated);
+        }
+        edge.variables.extend(variables);
+
+        Ok(edge)
+    }
+
+    fn evaluate_rule_variable(&self, name: &str, rule: &Rule, edge: &BuildEdge, build_variables: &HashMap<String, String>, depth: usize) -> String {
+        match name {
+            "in" => edge.inputs.join(" "),
+            "in_newline" => edge.inputs.join("\n"),
+            "out" => edge.outputs.join(" "),
+            _ => match (build_variables.get(name), rule.bindings.get(name)) {
+                (Some(value), _) => value.clone(),
+                // depth limit, rule variables referencing each other in a cycle would never end
+                (None, Some(value)) if depth < 16 => value.evaluate(&|variable| self.evaluate_rule_variable(variable, rule, edge, build_variables, depth + 1)),
+                _ => self.lookup_variable(name),
+            }
+        }
+    }
+
+    fn evaluate_in_scope(&self, value: &EvalString) -> String {
+        value.evaluate(&|variable| self.lookup_variable(variable))
+    }
+
+    fn lookup_variable(&self, name: &str) -> String {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.variables.get(name))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn lookup_rule(&self, name: &str) -> Option<&Rule> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.rules.get(name))
+    }
+
+    fn current_scope(&mut self) -> &mut Scope {
+        self.scopes.last_mut().unwrap()
+    }
+}
+
+// joins "$\n" continuations, drops comments and empty lines
+fn logical_lines(content: &str) -> Vec<Line> {
+    let mut lines: Vec<Line> = vec![];
+    let mut current: Option<Line> = None;
+
+    for (index, physical_line) in content.lines().enumerate() {
+        let physical_line = physical_line.strip_suffix('\r').unwrap_or(physical_line);
+
+        let text = match &current {
+            Some(_) => physical_line.trim_start(),
+            None => {
+                let trimmed = physical_line.trim_start();
+                if trimmed.is_empty() || trimmed.starts_with('#') { continue; }
+                current = Some(Line {
+                    number: index + 1,
+                    indented: trimmed.len() != physical_line.len(),
+                    text: String::new(),
+                });
+                trimmed
+            }
+        };
+
+        let line = current.as_mut().unwrap();
+        // odd number of trailing '$' -> the last one escapes the newline
+        let trailing_dollars = text.chars().rev().take_while(|character| *character == '$').count();
+        if trailing_dollars % 2 == 1 {
+            line.text.push_str(&text[..text.len() - 1]);
+        } else {
+            line.text.push_str(text);
+            lines.push(current.take().unwrap());
+        }
+    }
+    if let Some(line) = current { lines.push(line); }
+
+    lines
+}
+
+fn parse_binding(text: &str) -> Option<(String, EvalString)> {
+    let (name, value) = text.split_once('=')?;
+    let name = name.trim();
+    if name.is_empty() || !name.chars().all(|character| character.is_ascii_alphanumeric() || "_-.".contains(character)) {
+        return None;
+    }
+    Some((name.into(), parse_value(value.trim_start())))
+}
+
+fn parse_value(text: &str) -> EvalString {
+    let mut value = EvalString::default();
+    let mut characters = text.chars();
+    while let Some(character) = characters.next() {
+        if character == '$' {
+            parse_escape(&mut characters, &mut value);
+        } else {
+            value.push_literal(character);
+        }
+    }
+    value
+}
+
+// handles what comes after a '$'; Chars instead of Peekable so callers still know where they are (as_str)
+fn parse_escape(characters: &mut std::str::Chars<'_>, value: &mut EvalString) {
+    let is_name_character = |character: char| character.is_ascii_alphanumeric() || character == '_' || character == '-';
+    match characters.clone().next() {
+        Some('$') | Some(' ') | Some(':') => {
+            value.push_literal(characters.next().unwrap());
+        }
+        Some('{') => {
+            characters.next();
+            let name: String = characters.by_ref().take_while(|character| *character != '}').collect();
+            value.tokens.push(Token::Variable(name));
+        }
+        Some(character) if is_name_character(character) => {
+            let mut name = String::new();
+            while let Some(character) = characters.clone().next().filter(|character| is_name_character(*character)) {
+                characters.next();
+                name.push(character);
+            }
+            value.tokens.push(Token::Variable(name));
+        }
+        // invalid escape, ninja would fail here, keep it as is
+        _ => value.push_literal('$'),
+    }
+}
+
+enum PathItem {
+    Path(EvalString),
+    Separator(String),
+}
+
+// paths of a build/default line, stops at an unescaped ':' if stop_at_colon, returns the rest
+fn parse_paths(text: &str, stop_at_colon: bool) -> (Vec<PathItem>, &str) {
+    let mut items: Vec<PathItem> = vec![];
+    let mut characters = text.chars();
+    let mut current = EvalString::default();
+
+    while let Some(character) = characters.next() {
+        match character {
+            ' ' | '\t' => {
+                if !current.is_empty() { items.push(PathItem::Path(std::mem::take(&mut current))); }
+            }
+            ':' if stop_at_colon => {
+                if !current.is_empty() { items.push(PathItem::Path(current)); }
+                // the rest starts at the ':'
+                return (items, &text[text.len() - characters.as_str().len() - 1..]);
+            }
+            '|' if current.is_empty() => {
+                let separator = match characters.clone().next() {
+                    Some('|') => { characters.next(); "||" }
+                    Some('@') => { characters.next(); "|@" }
+                    _ => "|",
+                };
+                items.push(PathItem::Separator(separator.into()));
+            }
+            '$' => parse_escape(&mut characters, &mut current),
+            other => current.push_literal(other),
+        }
+    }
+    if !current.is_empty() { items.push(PathItem::Path(current)); }
+
+    (items, "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluated(value: &EvalString) -> String {
+        value.evaluate(&|name| format!("<{}>", name))
+    }
+
+    fn paths(items: &[PathItem]) -> Vec<String> {
+        items
+            .iter()
+            .map(|item| match item {
+                PathItem::Path(path) => evaluated(path),
+                PathItem::Separator(separator) => separator.clone(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn continuations_and_comments() {
+        let lines = logical_lines("# comment\nbuild out: rule $\n    in1 $\n  in2\n  var = a$$\n\nx = 1\r\n");
+        let texts: Vec<(&str, bool)> = lines.iter().map(|line| (line.text.as_str(), line.indented)).collect();
+        assert_eq!(texts, vec![("build out: rule in1 in2", false), ("var = a$$", true), ("x = 1", false)]);
+        assert_eq!(lines[1].number, 5);
+    }
+
+    #[test]
+    fn escapes_and_variables() {
+        assert_eq!(evaluated(&parse_value("a$ b$:c$$d $var/${other}.x $-z")), "a b:c$d <var>/<other>.x <-z>");
+        // invalid escape is kept
+        assert_eq!(evaluated(&parse_value("100$%")), "100$%");
+    }
+
+    #[test]
+    fn build_paths_and_separators() {
+        let (outputs, rest) = parse_paths("out$ 1 out$:2 | implicit: CARGO in", true);
+        assert_eq!(paths(&outputs), vec!["out 1", "out:2", "|", "implicit"]);
+        assert_eq!(rest, ": CARGO in");
+
+        let (inputs, rest) = parse_paths("a $dir/b | c || d |@ e", false);
+        assert_eq!(paths(&inputs), vec!["a", "<dir>/b", "|", "c", "||", "d", "|@", "e"]);
+        assert_eq!(rest, "");
+    }
+
+    #[test]
+    fn long_edge_stays_linear() {
+        let line: String = (0..20000).map(|index| format!("dir$ {}/file$:{} ", index, index)).collect();
+        let (items, _) = parse_paths(&line, false);
+        assert_eq!(items.len(), 20000);
+        assert_eq!(paths(&items[..1]), vec!["dir 0/file:0"]);
+    }
+
+    #[test]
+    fn build_edge_variables() {
+        let dir = std::env::temp_dir().join(format!("arielosbom-ninja-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("rules.ninja"), "rule CARGO\n  command = cd ${relroot} && cargo build $FEATURES && cp x $out\n").unwrap();
+        fs::write(dir.join("build.ninja"), concat!(
+            "relroot = app\n",
+            "include rules.ninja\n",
+            "build bin/board/app/app.elf | bin/board/app/app.map: CARGO src/main.rs || phony\n",
+            "    FEATURES = --features=a$ b\n",
+            "build phony: phony\n",
+            "default bin/board/app/app.elf\n",
+        )).unwrap();
+
+        let ninja_file = NinjaFile::load(Path::new("build.ninja"), &dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let edge = &ninja_file.builds[0];
+        assert_eq!(edge.outputs, vec!["bin/board/app/app.elf"]);
+        assert_eq!(edge.implicit_outputs, vec!["bin/board/app/app.map"]);
+        assert_eq!(edge.inputs, vec!["src/main.rs"]);
+        assert_eq!(edge.order_only_inputs, vec!["phony"]);
+        assert_eq!(edge.command(), Some("cd app && cargo build --features=a b && cp x bin/board/app/app.elf"));
+        assert_eq!(ninja_file.defaults, vec!["bin/board/app/app.elf"]);
+    }
+}
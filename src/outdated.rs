@@ -0,0 +1,94 @@
+use crate::{
+    bloat::{BloatData},
+    error::{Error},
+    registry::{RegistryIndex, is_crates_io_package},
+    sbom::{FileFormat, write_json_file},
+};
+
+use cargo_metadata::{Metadata};
+use semver::{Version, VersionReq};
+use serde::{Serialize};
+
+#[derive(Serialize)]
+pub struct OutdatedEntry {
+    pub name: String,
+    pub version: Version,
+    pub newest_compatible: Option<Version>,
+    pub newest: Option<Version>,
+    pub releases_behind: usize,
+}
+
+// only for crates that actually land in the executable, everything else isn't shipped anyway
+#[derive(Serialize)]
+pub struct OutdatedReport {
+    pub entries: Vec<OutdatedEntry>,
+}
+
+impl OutdatedReport {
+
+    pub fn generate(metadata: &Metadata, bloat_data: &BloatData, index: &RegistryIndex) -> OutdatedReport {
+
+        let mut entries: Vec<OutdatedEntry> = vec![];
+
+        for package in metadata.packages
+                            .iter()
+                            .filter(|package| is_crates_io_package(package))
+                            .filter(|package| bloat_data.is_linked(package)) {
+
+            let Some(index_versions) = index.versions(&package.name) else {
+                println!("{}@{} not found in the registry index, skipping", package.name, package.version);
+                continue;
+            };
+
+            // yanked and pre-releases aren't something one could/should update to
+            let candidates: Vec<&Version> = index_versions
+                                                .iter()
+                                                .filter(|index_version| !index_version.yanked)
+                                                .map(|index_version| &index_version.version)
+                                                .filter(|version| version.pre.is_empty() || !package.version.pre.is_empty())
+                                                .collect();
+
+            let compatible_requirement = VersionReq::parse(&format!("^{}", package.version)).ok();
+
+            entries.push(OutdatedEntry {
+                name: package.name.to_string(),
+                version: package.version.clone(),
+                newest_compatible: candidates
+                                    .iter()
+                                    .filter(|version| compatible_requirement.as_ref().is_some_and(|requirement| requirement.matches(version)))
+                                    .max()
+                                    .map(|version| (*version).clone()),
+                newest: candidates.iter().max().map(|version| (*version).clone()),
+                releases_behind: candidates.iter().filter(|version| ***version > package.version).count(),
+            });
+        }
+
+        entries.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+
+        OutdatedReport { entries }
+    }
+
+    pub fn print_table(&self) {
+
+        let name_width = self.entries.iter().map(|entry| entry.name.len()).max().unwrap_or(0).max("Name".len());
+        let optional_version = |version: &Option<Version>| match version {
+            Some(version) => version.to_string(),
+            None => "-".into(),
+        };
+
+        println!("{:<name_width$}  {:<12}  {:<12}  {:<12}  Behind", "Name", "Version", "Compatible", "Newest");
+        for entry in self.entries.iter() {
+            println!("{:<name_width$}  {:<12}  {:<12}  {:<12}  {}",
+                entry.name,
+                entry.version.to_string(),
+                optional_version(&entry.newest_compatible),
+                optional_version(&entry.newest),
+                entry.releases_behind
+            );
+        }
+    }
+
+    pub fn write_to_file(&self, file_name: &str) -> Result<(), Error> {
+        write_json_file(&self, &format!("./{}-outdated.{}", file_name, FileFormat::Json))
+    }
+}
@@ -0,0 +1,124 @@
+use cargo_metadata::{Package};
+use semver::{Version};
+use serde::{Deserialize};
+
+use std::{
+    env,
+    fs,
+    path::{Path, PathBuf},
+};
+
+// one line of the crates.io index, only what's needed so far
+#[derive(Deserialize, Clone, Debug)]
+pub struct IndexVersion {
+    #[serde(rename = "vers")]
+    pub version: Version,
+    #[serde(default)]
+    pub yanked: bool,
+}
+
+// works on both a plain checkout of the crates.io index and on cargo's own index cache
+// (~/.cargo/registry/index/<registry>/.cache), which exists for sparse and git registries
+pub struct RegistryIndex {
+    roots: Vec<PathBuf>,
+}
+
+impl RegistryIndex {
+
+    pub fn open(path: &Path) -> Option<RegistryIndex> {
+
+        if !path.is_dir() { return None; }
+
+        let mut roots: Vec<PathBuf> = vec![];
+
+        if is_index_root(path) {
+            roots.push(path.to_path_buf());
+        } else {
+            // e.g. ~/.cargo/registry/index with one dir per registry
+            let mut sub_roots: Vec<PathBuf> = fs::read_dir(path).ok()?
+                                                .filter_map(|entry| entry.ok())
+                                                .map(|entry| entry.path())
+                                                .filter(|sub_path| is_index_root(sub_path))
+                                                .collect();
+            // prefer the sparse index, it's what current cargo uses by default
+            sub_roots.sort_by_key(|sub_path| !sub_path.to_string_lossy().contains("index.crates.io"));
+            roots.append(&mut sub_roots);
+        }
+
+        match roots.is_empty() {
+            true => None,
+            false => Some(RegistryIndex { roots })
+        }
+    }
+
+    pub fn default_path() -> Option<PathBuf> {
+        cargo_home().map(|home| home.join("registry").join("index"))
+    }
+
+    // None if the crate isn't known to any of the index roots
+    pub fn versions(&self, crate_name: &str) -> Option<Vec<IndexVersion>> {
+        let relative_path = index_relative_path(crate_name);
+
+        for root in self.roots.iter() {
+            if let Ok(bytes) = fs::read(root.join(".cache").join(&relative_path)) {
+                return Some(parse_cache_file(&bytes));
+            }
+            if let Ok(content) = fs::read_to_string(root.join(&relative_path)) {
+                return Some(content.lines().filter_map(parse_index_line).collect());
+            }
+        }
+        None
+    }
+}
+
+pub fn cargo_home() -> Option<PathBuf> {
+    match env::var_os("CARGO_HOME") {
+        Some(home) => Some(PathBuf::from(home)),
+        None => env::var_os("HOME").map(|home| PathBuf::from(home).join(".cargo"))
+    }
+}
+
+pub fn is_crates_io_package(package: &Package) -> bool {
+    match &package.source {
+        Some(source) => source.is_crates_io() || source.repr == "sparse+https://index.crates.io/",
+        None => false
+    }
+}
+
+fn is_index_root(path: &Path) -> bool {
+    path.join(".cache").is_dir() || path.join("config.json").is_file()
+}
+
+// same layout as the index itself: 1/a, 2/ab, 3/a/abc, ab/cd/abcd...
+fn index_relative_path(crate_name: &str) -> PathBuf {
+    let name = crate_name.to_lowercase();
+    match name.len() {
+        1 => Path::new("1").join(&name),
+        2 => Path::new("2").join(&name),
+        3 => Path::new("3").join(&name[..1]).join(&name),
+        _ => Path::new(&name[..2]).join(&name[2..4]).join(&name),
+    }
+}
+
+fn parse_index_line(line: &str) -> Option<IndexVersion> {
+    serde_json::from_str(line).ok()
+}
+
+// cache file: [cache version: u8][index format version: u32][revision\0] then ([version]\0[json line]\0)*
+fn parse_cache_file(bytes: &[u8]) -> Vec<IndexVersion> {
+    let header_len = match bytes.first() {
+        Some(cache_version) if *cache_version >= 2 => 5,
+        Some(_) => 1,
+        None => return vec![]
+    };
+    if bytes.len() < header_len { return vec![]; }
+
+    bytes[header_len..]
+        .split(|byte| *byte == 0)
+        .skip(1)    // revision
+        .skip(1)    // first version string
+        .step_by(2)
+        .filter_map(|line| std::str::from_utf8(line).ok())
+        .filter_map(parse_index_line)
+        .collect()
+}
@@ -1,4 +1,12 @@
-use crate::component::{Component, Dependency};
+use crate::{
+    advisory::{AdvisoryDatabase},
+    bloat::{BloatData, Footprint},
+    component::{Component, Dependency, Property},
+    error::{Error},
+    linkmap::{MapFootprints},
+    registry::{RegistryIndex, is_crates_io_package},
+    target::{TargetInfo},
+};
 
 use cargo_lock::{Checksum, Lockfile};
 use cargo_metadata::{DependencyKind, Metadata};
@@ -6,12 +14,13 @@ use chrono::{NaiveDateTime, Utc};
 use serde::{Serialize, Deserialize};
 
 use std::{
-    collections::{HashMap},
+    collections::{BTreeMap, HashMap},
     fmt::{Formatter},
     fs::{File},
     io::{Write},
 };
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct SBOM {
 
@@ -27,6 +36,14 @@ pub struct SBOM {
     // maybe add dependencies as its own Vec<> later afterall for simpler serializing according to other formats as well
         // otherwise maybe diff struct for the other formats if that makes sense/is the better idea, will see
 
+    // only for aggregated SBOMs: one per board
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<BomVariant>,
+
+    // how complete the components are, per firmware image
+    #[serde(default)]
+    pub compositions: Vec<Composition>,
+
     #[serde(skip_serializing)]
     component_map: HashMap<String, usize>
 }
@@ -39,13 +56,123 @@ impl SBOM {
             bom_metadata: BomMetadata { 
                 creator: "ArielOSBOM".into(),
                 timestamp: Utc::now().naive_utc(),
+                app: None,
+                builder: None,
+                component: None,
+                tools: vec![],
+                properties: vec![],
+                target: None,
              },
             components: vec![],
+            variants: vec![],
+            compositions: vec![],
             component_map: HashMap::new()
         }
     }
 
-    pub fn convert_cargo_metadata_packages_to_components(&mut self, metadata: &Metadata, lockdata: &Lockfile) {
+    // product family SBOM out of per-board SBOMs: components deduplicated by id and hash,
+    // annotated with the boards they're in, every board as a variant
+    pub fn aggregate(format: BomFormat, boms: &[SBOM]) -> SBOM {
+
+        let mut aggregate = SBOM::new(format);
+        let mut component_boards: Vec<Vec<String>> = vec![];
+        let mut key_map: HashMap<(String, Vec<String>), usize> = HashMap::new();
+
+        for bom in boms.iter() {
+            let board = bom.bom_metadata.builder.clone().unwrap_or_else(|| "unknown".into());
+            let mut variant = BomVariant {
+                app: bom.bom_metadata.app.clone(),
+                builder: bom.bom_metadata.builder.clone(),
+                component: None,
+                target: bom.bom_metadata.target.clone(),
+                components: vec![],
+                properties: BTreeMap::new(),
+            };
+
+            // first where every component of the board ends up, so references can be renamed along
+            let mut indices: Vec<usize> = vec![];
+            let mut added: Vec<bool> = vec![];
+            for component in bom.components.iter() {
+                let key = (component.id.clone(), component.identifiers.clone());
+                let index = match key_map.get(&key) {
+                    Some(index) => *index,
+                    None => {
+                        let mut new_component = component.clone();
+                        // same id but different hash -> not the same component
+                        if aggregate.component_map.contains_key(&new_component.id) {
+                            new_component.id = format!("{}#{}", new_component.id, board);
+                        }
+                        let index = aggregate.components.len();
+                        aggregate.component_map.insert(new_component.id.clone(), index);
+                        aggregate.components.push(new_component);
+                        component_boards.push(vec![]);
+                        key_map.insert(key, index);
+                        index
+                    }
+                };
+                added.push(component_boards[index].is_empty());
+                component_boards[index].push(board.clone());
+                indices.push(index);
+            }
+            let ids: HashMap<&String, String> = bom.components
+                                                    .iter()
+                                                    .zip(indices.iter())
+                                                    .map(|(component, index)| (&component.id, aggregate.components[*index].id.clone()))
+                                                    .collect();
+            let rename = |id: &String| ids.get(id).cloned().unwrap_or_else(|| id.clone());
+
+            for ((component, index), added) in bom.components.iter().zip(indices).zip(added) {
+                let aggregated = &mut aggregate.components[index];
+                // edges of the board, merged with the other boards' for components they share
+                if added {
+                    aggregated.parent = component.parent.as_ref().map(rename);
+                    aggregated.dependencies.clear();
+                }
+                for dependency in component.dependencies.iter() {
+                    let dependency = Dependency { id: rename(&dependency.id), build: dependency.build };
+                    if !aggregated.dependencies.contains(&dependency) { aggregated.dependencies.push(dependency); }
+                }
+                // sizes etc. of this board's build, kept with the variant instead of the first board's on the component
+                aggregated.properties.retain(|property| !is_build_property(&property.name));
+                let build_properties: Vec<Property> = component.properties.iter().filter(|property| is_build_property(&property.name)).cloned().collect();
+                if !build_properties.is_empty() { variant.properties.insert(aggregated.id.clone(), build_properties); }
+
+                if bom.bom_metadata.component.as_ref() == Some(&component.id) {
+                    variant.component = Some(aggregated.id.clone());
+                }
+                variant.components.push(aggregated.id.clone());
+            }
+
+            for composition in bom.compositions.iter() {
+                aggregate.compositions.push(Composition {
+                    assemblies: variant.components.clone(),
+                    builder: bom.bom_metadata.builder.clone(),
+                    ..composition.clone()
+                });
+            }
+
+            aggregate.variants.push(variant);
+        }
+
+        for (component, boards) in aggregate.components.iter_mut().zip(component_boards) {
+            component.properties.push(Property::new("arielosbom:boards", boards.join(",")));
+        }
+
+        if let Some(first) = boms.first()
+            && boms.iter().all(|bom| bom.bom_metadata.app == first.bom_metadata.app) {
+            aggregate.bom_metadata.app = first.bom_metadata.app.clone();
+        }
+        for tool in boms.iter().flat_map(|bom| bom.bom_metadata.tools.iter()) {
+            if !aggregate.bom_metadata.tools.contains(tool) { aggregate.bom_metadata.tools.push(tool.clone()); }
+        }
+
+        let boards: Vec<String> = aggregate.variants.iter().filter_map(|variant| variant.builder.clone()).collect();
+        aggregate.bom_metadata.properties.push(Property::new("arielosbom:boards", boards.join(",")));
+
+        aggregate
+    }
+
+    pub fn convert_cargo_metadata_packages_to_components(&mut self, metadata: &Metadata, lockdata: &Lockfile) -> Result<(), Error> {
         
         // map Cargo.lock checksums to packages
         let mut lock_hash: HashMap<(String, String), Checksum> = HashMap::new();
@@ -55,17 +182,17 @@ impl SBOM {
             }
         }
 
-        assert!(metadata.packages.len() == metadata.resolve.as_ref().unwrap().nodes.len());
-        let mut index = 0;
-        for package in metadata.packages.iter() {
+        let resolve = match &metadata.resolve {
+            Some(resolve) if resolve.nodes.len() == metadata.packages.len() => resolve,
+            Some(_) => return Err(Error::MetadataContent { reason: "number of resolve nodes and packages differ".into() }),
+            None => return Err(Error::MetadataContent { reason: "no dependency resolve graph".into() }),
+        };
+        for (index, package) in metadata.packages.iter().enumerate() {
             self.components
                 .push(Component::create_component_from_metadata(
                     package, 
                     lock_hash.get(&(package.name.to_string(), package.version.to_string())),
-                    metadata.resolve
-                        .as_ref()
-                        .unwrap()
-                        .nodes[index].deps
+                    resolve.nodes[index].deps
                         .iter()
                         .map(|dep| Dependency {
                             id: dep.pkg.repr.clone(), 
@@ -77,37 +204,248 @@ impl SBOM {
                         .collect()
                 ));
             self.component_map.insert(package.id.repr.clone(), index);
-            index += 1;
+        }
+        Ok(())
+    }
+
+    // components that don't come from cargo metadata (toolchain, ...)
+    // parents (if they are components) depend on what they're the parent of
+    pub fn add_components(&mut self, components: Vec<Component>) {
+        for component in components {
+            if let Some(&parent_index) = component.parent.as_ref().and_then(|parent| self.component_map.get(parent)) {
+                self.components[parent_index].dependencies.push(Dependency { id: component.id.clone(), build: false });
+            }
+            self.component_map.insert(component.id.clone(), self.components.len());
+            self.components.push(component);
+        }
+    }
+
+    // only known with debug info in the firmware
+    pub fn add_code_sizes(&mut self, metadata: &Metadata, bloat_data: &BloatData) {
+        for package in metadata.packages.iter() {
+            let (Some(&component_index), Some(size)) = (self.component_map.get(&package.id.repr), bloat_data.package_size(package)) else { continue };
+            self.components[component_index].properties.push(Property::new("arielosbom:code_bytes", size));
+        }
+    }
+
+    // flash/RAM per package from the symbols (or cargo bloat's .text), the image's from its sections
+    pub fn add_footprints(&mut self, metadata: &Metadata, bloat_data: &BloatData) {
+        let text_only = bloat_data.is_text_only();
+        for package in metadata.packages.iter() {
+            let (Some(&component_index), Some(footprint)) = (self.component_map.get(&package.id.repr), bloat_data.package_footprint(package)) else { continue };
+            self.components[component_index].properties.extend(footprint.properties(text_only));
+        }
+
+        // cargo bloat's text section is the image_bytes of the composition already
+        let image_footprint = bloat_data.image_footprint();
+        match text_only {
+            true => println!("Footprint: {} bytes of code (cargo bloat, no data sections)", image_footprint.text),
+            false => {
+                println!("Footprint: {} bytes of flash, {} bytes of RAM", image_footprint.flash(), image_footprint.ram());
+                self.bom_metadata.properties.extend(image_footprint.properties(false));
+            }
+        }
+    }
+
+    // the linker map knows the object files, so it replaces the symbol based footprints and also covers native libraries;
+    // components the map has nothing for keep theirs
+    pub fn set_map_footprints(&mut self, footprints: &MapFootprints) {
+        for (id, footprint) in footprints.components.iter() {
+            let Some(&component_index) = self.component_map.get(id) else { continue };
+            let properties = &mut self.components[component_index].properties;
+            properties.retain(|property| !Footprint::is_property(&property.name));
+            properties.extend(footprint.properties(false));
+        }
+
+        println!("Footprint from linker map: {} bytes of flash, {} bytes of RAM", footprints.total.flash(), footprints.total.ram());
+        self.bom_metadata.properties.retain(|property| !Footprint::is_property(&property.name));
+        self.bom_metadata.properties.extend(footprints.total.properties(false));
+        self.bom_metadata.properties.push(Property::new("arielosbom:linker_map", footprints.path.display()));
+    }
+
+    // crates in the firmware no package could be matched to
+    pub fn add_unmatched_crates(&mut self, unmatched_crates: &[(String, u64)]) {
+        if unmatched_crates.is_empty() { return; }
+        let names: Vec<&str> = unmatched_crates.iter().map(|(name, _)| name.as_str()).collect();
+        self.bom_metadata.properties.push(Property::new("arielosbom:unmatched_crates", names.join(",")));
+    }
+
+    // what of the image the components don't explain ([Unknown], toolchain crates, crates without a package);
+    // nothing unexplained still doesn't make the SBOM complete (non-Rust parts etc.), so that's "unknown"
+    pub fn add_composition(&mut self, bloat_data: &BloatData) {
+        let unattributed: Vec<UnattributedEntry> = bloat_data.unmatched_crates()
+                                                    .iter()
+                                                    .map(|(name, size)| UnattributedEntry { name: name.clone(), size: *size })
+                                                    .collect();
+
+        let unattributed_size = bloat_data.unmatched_size();
+        let total_size = bloat_data.total_size();
+        self.bom_metadata.properties.push(Property::new("arielosbom:image_bytes", total_size));
+        self.bom_metadata.properties.push(Property::new("arielosbom:unattributed_bytes", unattributed_size));
+        if total_size > 0 {
+            self.bom_metadata.properties.push(Property::new("arielosbom:unattributed_share",
+                format!("{:.2}%", unattributed_size as f64 * 100.0 / total_size as f64)));
+        }
+
+        self.compositions.push(Composition {
+            aggregate: match unattributed.is_empty() {
+                true => CompositionAggregate::Unknown,
+                false => CompositionAggregate::Incomplete,
+            },
+            assemblies: self.components.iter().map(|component| component.id.clone()).collect(),
+            unattributed,
+            builder: None,
+        });
+    }
+
+    // only crates.io packages, everything else can't be yanked/has no advisories
+    pub fn flag_yanked_and_unmaintained(&mut self, metadata: &Metadata, index: Option<&RegistryIndex>, advisories: Option<&AdvisoryDatabase>) {
+
+        let mut yanked: Vec<String> = vec![];
+        let mut unmaintained: Vec<String> = vec![];
+
+        for package in metadata.packages.iter().filter(|package| is_crates_io_package(package)) {
+            let Some(&component_index) = self.component_map.get(&package.id.repr) else { continue };
+            let component = &mut self.components[component_index];
+
+            // no statement if the local index doesn't know the version (e.g. outdated cache)
+            if let Some(index_version) = index
+                                            .and_then(|index| index.versions(&package.name))
+                                            .and_then(|versions| versions.into_iter().find(|version| version.version == package.version)) {
+                component.properties.push(Property::new("arielosbom:yanked", index_version.yanked));
+                if index_version.yanked { yanked.push(format!("{}@{}", package.name, package.version)); }
+            }
+
+            if let Some(advisories) = advisories {
+                let advisory_ids: Vec<String> = advisories
+                                                    .unmaintained(&package.name, &package.version)
+                                                    .iter()
+                                                    .map(|advisory| advisory.id.clone())
+                                                    .collect();
+                if !advisory_ids.is_empty() {
+                    component.properties.push(Property::new("arielosbom:unmaintained", advisory_ids.join(",")));
+                    unmaintained.push(format!("{}@{} ({})", package.name, package.version, advisory_ids.join(", ")));
+                }
+            }
+        }
+
+        if index.is_some() {
+            println!("Yanked components: {}", yanked.len());
+            for component in yanked.iter() { println!("    {}", component); }
+            self.bom_metadata.properties.push(Property::new("arielosbom:yanked_count", yanked.len()));
+            self.bom_metadata.properties.push(Property::new("arielosbom:yanked_components", yanked.join(", ")));
+        }
+        if advisories.is_some() {
+            println!("Unmaintained components: {}", unmaintained.len());
+            for component in unmaintained.iter() { println!("    {}", component); }
+            self.bom_metadata.properties.push(Property::new("arielosbom:unmaintained_count", unmaintained.len()));
+            self.bom_metadata.properties.push(Property::new("arielosbom:unmaintained_components", unmaintained.join(", ")));
         }
     }
 
     // file format as input later maybe to loop through calls of this function?
-    pub fn write_to_file(&self, file_name: &str) {
-        let file_format = FileFormat::Json;
-        let mut file = match File::create(format!("./{}.{}", file_name, file_format)) {
-            Ok(file) => file,
-            Err(e) => panic!("Could not create file: {}.{}: {}", file_name, file_format, e),
-        };
+    pub fn write_to_file(&self, file_name: &str) -> Result<(), Error> {
+        write_json_file(&self, &format!("./{}.{}", file_name, FileFormat::Json))
+    }
+
+}
+
+// what depends on the build of the board rather than on the component itself
+fn is_build_property(name: &str) -> bool {
+    ["arielosbom:code_bytes", "arielosbom:yanked", "arielosbom:unmaintained"].contains(&name) || Footprint::is_property(name)
+}
+
+pub fn write_json_file<T: Serialize>(content: &T, path: &str) -> Result<(), Error> {
+    let mut file = match File::create(path) {
+        Ok(file) => file,
+        Err(e) => return Err(Error::Output { path: path.into(), source: e }),
+    };
 
-        file.write_all(serde_json::
-                            to_string(&self)
-                            .unwrap()
-                            .as_bytes()
-                        ).expect("Could not write SBOM data to file.");
+    let serialized = match serde_json::to_string(content) {
+        Ok(serialized) => serialized,
+        Err(e) => return Err(Error::Serialize { source: e }),
+    };
+
+    match file.write_all(serialized.as_bytes()) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(Error::Output { path: path.into(), source: e }),
     }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct BomVariant {
+    pub app: Option<String>,
+    pub builder: Option<String>,
+    // the board's firmware component
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub component: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<TargetInfo>,
+    // ids of the aggregate's components in this variant
+    pub components: Vec<String>,
+    // the board's build properties (sizes, footprints, yanked, ...) by component id
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub properties: BTreeMap<String, Vec<Property>>,
+}
 
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Tool {
+    pub name: String,
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<Property>,
+}
+
+// CycloneDX compositions, SPDX would express the same as NOASSERTION
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Composition {
+    pub aggregate: CompositionAggregate,
+    // ids of the components the statement is about
+    pub assemblies: Vec<String>,
+    // parts of the image no component accounts for
+    pub unattributed: Vec<UnattributedEntry>,
+    // only in aggregated SBOMs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub builder: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CompositionAggregate {
+    Complete,
+    Incomplete,
+    Unknown,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct UnattributedEntry {
+    pub name: String,
+    pub size: u64,
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct BomMetadata {
     creator: String,
     timestamp: NaiveDateTime,
-    // target
+    // laze app and builder (board) the SBOM was generated for
+    pub app: Option<String>,
+    pub builder: Option<String>,
+    // id of the root component, the firmware
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub component: Option<String>,
+    // compiler, LLVM, linker
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<Tool>,
+    properties: Vec<Property>,
+    // triple, CPU, chip and laze contexts the firmware was built for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<TargetInfo>,
     // other BomFormat related metadata
     // other general project related data? (features, protocols, program size, ...)
 }
 
 // potentially changing serialization later for diff. formats; or as mentioned just make this based off. diff structs altogether
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub enum BomFormat {
     Raw,
@@ -0,0 +1,96 @@
+use crate::{
+    bloat::{Footprint},
+    component::{Component},
+    error::{Error},
+    linkmap::{MapFootprints},
+    sbom::{FileFormat, write_json_file},
+};
+
+use serde::{Serialize};
+
+#[derive(Serialize)]
+pub struct SizeEntry {
+    pub name: String,
+    // None for what no component accounts for
+    pub component: Option<String>,
+    pub version: Option<String>,
+    pub flash: u64,
+    pub ram: u64,
+    pub text: u64,
+    pub rodata: u64,
+    pub data: u64,
+    pub bss: u64,
+}
+
+impl SizeEntry {
+
+    fn new(name: String, component: Option<&Component>, footprint: &Footprint) -> SizeEntry {
+        SizeEntry {
+            name,
+            component: component.map(|component| component.id.clone()),
+            version: component.map(|component| component.version.to_string()),
+            flash: footprint.flash(),
+            ram: footprint.ram(),
+            text: footprint.text,
+            rodata: footprint.rodata,
+            data: footprint.data,
+            bss: footprint.bss,
+        }
+    }
+}
+
+// what every component costs in flash/RAM according to the linker map, biggest first
+#[derive(Serialize)]
+pub struct SizeReport {
+    pub map: String,
+    pub entries: Vec<SizeEntry>,
+    pub total: SizeEntry,
+}
+
+impl SizeReport {
+
+    pub fn generate(footprints: &MapFootprints, components: &[Component]) -> SizeReport {
+
+        let mut entries: Vec<SizeEntry> = footprints.components
+                                            .iter()
+                                            .map(|(id, footprint)| {
+                                                let component = components.iter().find(|component| component.id == *id);
+                                                let name = component.map(|component| component.name.clone()).unwrap_or_else(|| id.clone());
+                                                SizeEntry::new(name, component, footprint)
+                                            })
+                                            .collect();
+        entries.extend(footprints.unattributed
+                        .iter()
+                        .map(|(name, footprint)| SizeEntry::new(format!("[{}]", name), None, footprint)));
+        entries.sort_by(|a, b| b.flash.cmp(&a.flash).then_with(|| b.ram.cmp(&a.ram)).then_with(|| a.name.cmp(&b.name)));
+
+        SizeReport {
+            map: footprints.path.display().to_string(),
+            entries,
+            total: SizeEntry::new("Total".into(), None, &footprints.total),
+        }
+    }
+
+    pub fn print_table(&self) {
+
+        let name_width = self.entries.iter().map(|entry| entry.name.len()).max().unwrap_or(0).max("Name".len());
+
+        println!("{:<name_width$}  {:<12}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}", "Name", "Version", "Flash", "RAM", ".text", ".rodata", ".data", ".bss");
+        for entry in self.entries.iter().chain([&self.total]) {
+            println!("{:<name_width$}  {:<12}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}",
+                entry.name,
+                entry.version.as_deref().unwrap_or("-"),
+                entry.flash,
+                entry.ram,
+                entry.text,
+                entry.rodata,
+                entry.data,
+                entry.bss
+            );
+        }
+    }
+
+    pub fn write_to_file(&self, file_name: &str) -> Result<(), Error> {
+        write_json_file(&self, &format!("./{}-size.{}", file_name, FileFormat::Json))
+    }
+}
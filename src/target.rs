@@ -0,0 +1,163 @@
+use crate::{
+    component::{Component, Property},
+    invocation::{CargoInvocation},
+    laze::{LazeProject, is_default_context},
+    linker,
+    toolchain::{command_output},
+};
+
+use serde::{Deserialize, Serialize};
+
+use std::{
+    path::{Path},
+};
+
+// variable Ariel OS sets in the chip's context for flashing/debugging
+const PROBE_RS_CHIP: &str = "PROBE_RS_CHIP";
+
+// what the firmware was built for: triple and CPU from the cargo build, chip and contexts from laze
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct TargetInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub triple: Option<String>,
+    // -C target-cpu
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<String>,
+    // target_feature cfgs rustc enables for the triple with the build's -C target-cpu/-C target-feature
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cpu_features: Vec<String>,
+    // the laze context of the MCU and the one above it, e.g. nrf52840 and nrf52
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chip: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chip_family: Option<String>,
+    // e.g. nRF52840_xxAA
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probe_rs_chip: Option<String>,
+    // enabled laze contexts, most general first, the builder last
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub contexts: Vec<String>,
+}
+
+impl TargetInfo {
+
+    // None if neither laze nor the build environment tell the chip
+    pub fn device_id(&self) -> Option<String> {
+        self.device_name().map(|name| format!("device+{}", name))
+    }
+
+    fn device_name(&self) -> Option<&String> {
+        self.chip.as_ref().or(self.probe_rs_chip.as_ref())
+    }
+
+    // the MCU, same for every board with that chip
+    pub fn to_device_component(&self) -> Option<Component> {
+        let mut device = Component::create_device_component(self.device_id()?, self.device_name()?);
+        let properties = [
+            ("arielosbom:chip_family", self.chip_family.clone()),
+            ("arielosbom:probe_rs_chip", self.probe_rs_chip.clone()),
+            ("arielosbom:target", self.triple.clone()),
+            ("arielosbom:cpu", self.cpu.clone()),
+            ("arielosbom:cpu_features", (!self.cpu_features.is_empty()).then(|| self.cpu_features.join(","))),
+        ];
+        device.properties.extend(properties.into_iter().filter_map(|(name, value)| Some(Property::new(name, value?))));
+        Some(device)
+    }
+}
+
+// contexts from the builder's chain in the laze files, otherwise from the --cfg context="..." laze puts into RUSTFLAGS
+pub fn target_info(project_root: &Path, invocation: &CargoInvocation, laze_project: &LazeProject) -> TargetInfo {
+    let rustflags = linker::rustflags(project_root, invocation);
+    let triple = linker::target_triple(project_root, invocation);
+    let cpu = codegen_values(&rustflags, "target-cpu").pop();
+    let explicit_features: Vec<String> = codegen_values(&rustflags, "target-feature");
+    // without a target rustc would tell the host's features, which say nothing about the firmware
+    let cpu_features = match &triple {
+        Some(triple) => rustc_target_features(project_root, invocation, triple, cpu.as_deref(), &explicit_features)
+                            .unwrap_or_else(|| explicit_features.iter().flat_map(|features| features.split(',')).map(|feature| feature.to_string()).collect()),
+        None => vec![],
+    };
+
+    let chain = invocation.builder.as_deref().map(|builder| laze_project.context_chain(builder)).unwrap_or_default();
+    if let Some(builder) = &invocation.builder
+        && chain.is_empty() && !laze_project.is_empty() {
+        println!("Builder {} not found in the laze files, contexts from RUSTFLAGS only", builder);
+    }
+
+    // the context setting PROBE_RS_CHIP, the builder's parent if the board itself sets it or nothing does
+    let chip_index = chain
+                        .iter()
+                        .position(|context| context.env_value(PROBE_RS_CHIP).is_some())
+                        .filter(|index| *index > 0)
+                        .or_else(|| (chain.len() > 1).then_some(1));
+    let chip = chip_index.and_then(|index| chain.get(index)).filter(|context| !is_default_context(&context.name));
+    let chip_family = chip_index.and_then(|index| chain.get(index + 1)).filter(|context| !is_default_context(&context.name));
+    let probe_rs_chip = chain
+                            .iter()
+                            .find_map(|context| context.env_value(PROBE_RS_CHIP))
+                            .or_else(|| invocation.env_value(PROBE_RS_CHIP))
+                            .map(|chip| chip.to_string());
+
+    let contexts: Vec<String> = match chain.is_empty() {
+        true => cfg_contexts(&rustflags),
+        false => chain.iter().rev().map(|context| context.name.clone()).collect(),
+    };
+
+    TargetInfo {
+        triple,
+        cpu,
+        cpu_features,
+        chip: chip.map(|context| context.name.clone()),
+        chip_family: chip_family.map(|context| context.name.clone()),
+        probe_rs_chip,
+        contexts,
+    }
+}
+
+// -C <name>=X and -C<name>=X, in order
+fn codegen_values(rustflags: &[String], name: &str) -> Vec<String> {
+    let mut values: Vec<String> = vec![];
+    let mut flags = rustflags.iter();
+    while let Some(flag) = flags.next() {
+        let option = match flag.as_str() {
+            "-C" => flags.next().map(|option| option.as_str()),
+            _ => flag.strip_prefix("-C"),
+        };
+        if let Some((option_name, value)) = option.and_then(|option| option.split_once('='))
+            && option_name == name {
+            values.push(value.to_string());
+        }
+    }
+    values
+}
+
+// --cfg context="nrf52840" (quotes escaped or not), in RUSTFLAGS' order without duplicates
+fn cfg_contexts(rustflags: &[String]) -> Vec<String> {
+    let mut contexts: Vec<String> = vec![];
+    let mut flags = rustflags.iter();
+    while let Some(flag) = flags.next() {
+        let cfg = match flag.as_str() {
+            "--cfg" => flags.next().map(|cfg| cfg.as_str()),
+            _ => flag.strip_prefix("--cfg="),
+        };
+        if let Some(context) = cfg.and_then(|cfg| cfg.strip_prefix("context=")) {
+            let context = context.trim_matches(['"', '\\']).to_string();
+            if !context.is_empty() && !contexts.contains(&context) { contexts.push(context); }
+        }
+    }
+    contexts
+}
+
+// rustc knows what the triple and CPU imply, the rustflags only name what's added on top
+fn rustc_target_features(project_root: &Path, invocation: &CargoInvocation, triple: &str, cpu: Option<&str>, features: &[String]) -> Option<Vec<String>> {
+    let mut command = invocation.tool_command(project_root, "rustc");
+    command.args(["--print", "cfg", "--target", triple]);
+    if let Some(cpu) = cpu { command.arg(format!("-Ctarget-cpu={}", cpu)); }
+    for feature in features { command.arg(format!("-Ctarget-feature={}", feature)); }
+    let cfgs = command_output(&mut command)?;
+    Some(cfgs
+            .lines()
+            .filter_map(|line| line.strip_prefix("target_feature=\"")?.strip_suffix('"'))
+            .map(|feature| feature.to_string())
+            .collect())
+}
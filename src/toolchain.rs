@@ -0,0 +1,216 @@
+use crate::{
+    component::{Component, Dependency, Property},
+    invocation::{BuildOptions, CargoInvocation},
+    sbom::{Tool},
+};
+
+use cargo_lock::{Lockfile};
+use semver::{Version};
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    process::{Command},
+};
+
+// crates of the toolchain, not cargo packages unless std is built from source; the sysroot's Cargo.lock tells
+// the rest, without it (rust-src not installed) only these are recognized as the standard library's
+const SYSROOT_CRATES: [&str; 10] = ["std", "core", "alloc", "compiler_builtins", "panic_abort", "panic_unwind", "unwind", "std_detect", "proc_macro", "test"];
+
+// allocator shims rustc generates, in the firmware but in no library
+pub const SHIM_CRATE: &str = "__rustc";
+
+const STD_LICENSE: &str = "MIT OR Apache-2.0";
+
+// the rustc the firmware was built with (rustc -vV) and the linker it used
+pub struct Toolchain {
+    pub release: String,
+    pub commit_hash: Option<String>,
+    pub commit_date: Option<String>,
+    pub host: Option<String>,
+    pub llvm_version: Option<String>,
+    pub sysroot: Option<PathBuf>,
+    // name/path and first line of --version
+    pub linker: Option<(String, Option<String>)>,
+}
+
+impl Toolchain {
+
+    // same toolchain and environment as the build, None if rustc can't be run
+    pub fn detect(project_root: &Path, invocation: &CargoInvocation) -> Option<Toolchain> {
+
+        let version_output = command_output(invocation.tool_command(project_root, "rustc").arg("-vV"))?;
+        let fields: HashMap<&str, &str> = version_output
+                                            .lines()
+                                            .filter_map(|line| line.split_once(": "))
+                                            .collect();
+
+        let sysroot = command_output(invocation.tool_command(project_root, "rustc").args(["--print", "sysroot"]))
+                        .map(|sysroot| PathBuf::from(sysroot.trim()));
+
+        let mut toolchain = Toolchain {
+            release: fields.get("release")?.to_string(),
+            commit_hash: fields.get("commit-hash").filter(|hash| **hash != "unknown").map(|hash| hash.to_string()),
+            commit_date: fields.get("commit-date").filter(|date| **date != "unknown").map(|date| date.to_string()),
+            host: fields.get("host").map(|host| host.to_string()),
+            llvm_version: fields.get("LLVM version").map(|version| version.to_string()),
+            sysroot,
+            linker: None,
+        };
+        toolchain.linker = toolchain.detect_linker(project_root, invocation);
+
+        Some(toolchain)
+    }
+
+    // explicitly configured linker, otherwise rustc's default: rust-lld for bare metal targets, cc for the rest
+    fn detect_linker(&self, project_root: &Path, invocation: &CargoInvocation) -> Option<(String, Option<String>)> {
+        let bare_metal = invocation.target_triple().is_some_and(|target| target.contains("-none"));
+
+        let (name, mut command) = match invocation.linker() {
+            Some(linker) => (linker.clone(), invocation.tool_command(project_root, linker)),
+            None if bare_metal => {
+                let rust_lld = self.sysroot.as_ref()?.join("lib/rustlib").join(self.host.as_ref()?).join("bin/rust-lld");
+                let mut command = invocation.tool_command(project_root, rust_lld);
+                command.args(["-flavor", "gnu"]);
+                ("rust-lld".into(), command)
+            }
+            None => ("cc".into(), invocation.tool_command(project_root, "cc")),
+        };
+        command.arg("--version");
+
+        let version = command_output(&mut command).and_then(|output| output.lines().next().map(|line| line.trim().to_string()));
+        Some((name, version))
+    }
+
+    pub fn tools(&self) -> Vec<Tool> {
+        let mut rustc = Tool { name: "rustc".into(), version: self.release.clone(), properties: vec![] };
+        if let Some(commit_hash) = &self.commit_hash { rustc.properties.push(Property::new("arielosbom:commit_hash", commit_hash)); }
+        if let Some(commit_date) = &self.commit_date { rustc.properties.push(Property::new("arielosbom:commit_date", commit_date)); }
+        if let Some(host) = &self.host { rustc.properties.push(Property::new("arielosbom:host", host)); }
+
+        let mut tools = vec![rustc];
+        if let Some(llvm_version) = &self.llvm_version {
+            tools.push(Tool { name: "LLVM".into(), version: llvm_version.clone(), properties: vec![] });
+        }
+        if let Some((linker, version)) = &self.linker {
+            tools.push(Tool { name: linker.clone(), version: version.clone().unwrap_or_else(|| "unknown".into()), properties: vec![] });
+        }
+        tools
+    }
+
+    // <sysroot>/lib/rustlib/src/rust/library/Cargo.lock, needs the rust-src component (which -Z build-std needs anyway)
+    fn library_lock(&self) -> Option<Lockfile> {
+        let lock_path = self.sysroot.as_ref()?.join("lib/rustlib/src/rust/library/Cargo.lock");
+        Lockfile::load(lock_path).ok()
+    }
+
+    // standard library crates in the firmware: -Z build-std crates and linked toolchain crates, plus their
+    // linked dependencies from the library's Cargo.lock
+    pub fn std_components(&self, build_options: &BuildOptions, is_linked: impl Fn(&str) -> bool) -> Vec<Component> {
+
+        let release_version = Version::parse(&self.release).ok();
+        let id_prefix = format!("sysroot+rustc-{}", self.release);
+        let source_uri = || self.commit_hash.as_ref().map(|hash| format!("https://github.com/rust-lang/rust/tree/{}/library", hash));
+        // cargo always builds compiler_builtins along with -Z build-std
+        let mut build_std = build_options.build_std().unwrap_or_default();
+        if !build_std.is_empty() { build_std.push("compiler_builtins".into()); }
+
+        let Some(lockfile) = self.library_lock() else {
+            println!("No Cargo.lock in the toolchain's sysroot (rust-src not installed?), standard library crates without dependencies");
+            let Some(release_version) = release_version else { return vec![] };
+            return SYSROOT_CRATES
+                    .iter()
+                    .filter(|name| is_linked(name) || build_std.iter().any(|crate_name| crate_name == *name))
+                    .map(|name| {
+                        let mut component = Component::create_toolchain_component(
+                            format!("{}#{}@{}", id_prefix, name, release_version),
+                            name,
+                            release_version.clone(),
+                            None,
+                            source_uri(),
+                            Some(STD_LICENSE.into()),
+                            vec![]);
+                        self.add_commit_property(&mut component);
+                        component
+                    })
+                    .collect();
+        };
+
+        // the library has some crates in several versions (e.g. from crates.io and in-tree), so by name and version
+        type Key = (String, Version);
+        let key = |name: &cargo_lock::Name, version: &Version| -> Key { (name.as_str().to_string(), version.clone()) };
+        let packages: HashMap<Key, &cargo_lock::Package> = lockfile.packages
+                                                                .iter()
+                                                                .map(|package| (key(&package.name, &package.version), package))
+                                                                .collect();
+        let crate_name = |name: &str| name.replace("-", "_");
+        let in_tree = |package: &cargo_lock::Package| package.source.is_none();
+
+        let mut included: HashSet<Key> = HashSet::new();
+        let mut queue: Vec<Key> = packages
+                                    .keys()
+                                    .filter(|(name, _)| build_std.contains(name) || is_linked(&crate_name(name)))
+                                    .cloned()
+                                    .collect();
+        while let Some(package_key) = queue.pop() {
+            if !included.insert(package_key.clone()) { continue; }
+            for dependency in packages[&package_key].dependencies.iter() {
+                let dependency_key = key(&dependency.name, &dependency.version);
+                let Some(package) = packages.get(&dependency_key) else { continue };
+                // the lock file has dev/build/target specific dependencies as well, so only what's in the firmware
+                // and the rustc-std-workspace-* shims (pure re-exports, no code of their own)
+                if is_linked(&crate_name(dependency.name.as_str())) || (in_tree(package) && dependency.name.as_str().starts_with("rustc-std-workspace-")) {
+                    queue.push(dependency_key);
+                }
+            }
+        }
+
+        let version_of = |package: &cargo_lock::Package| -> Version {
+            match (in_tree(package) && package.version == Version::new(0, 0, 0), &release_version) {
+                (true, Some(release_version)) => release_version.clone(),
+                _ => package.version.clone(),
+            }
+        };
+        let id_of = |package: &cargo_lock::Package| format!("{}#{}@{}", id_prefix, package.name, version_of(package));
+
+        let mut keys: Vec<&Key> = included.iter().collect();
+        keys.sort();
+        keys
+            .into_iter()
+            .map(|package_key| {
+                let package = packages[package_key];
+                let dependencies: Vec<Dependency> = package.dependencies
+                                                        .iter()
+                                                        .map(|dependency| key(&dependency.name, &dependency.version))
+                                                        .filter(|dependency_key| included.contains(dependency_key))
+                                                        .map(|dependency_key| Dependency { id: id_of(packages[&dependency_key]), build: false })
+                                                        .collect();
+                let mut component = Component::create_toolchain_component(
+                    id_of(package),
+                    &package_key.0,
+                    version_of(package),
+                    package.checksum.as_ref(),
+                    match in_tree(package) {
+                        true => source_uri(),
+                        false => None,
+                    },
+                    in_tree(package).then(|| STD_LICENSE.into()),
+                    dependencies);
+                if in_tree(package) { self.add_commit_property(&mut component); }
+                component
+            })
+            .collect()
+    }
+
+    fn add_commit_property(&self, component: &mut Component) {
+        if let Some(commit_hash) = &self.commit_hash {
+            component.properties.push(Property::new("arielosbom:rustc_commit_hash", commit_hash));
+        }
+    }
+}
+
+pub fn command_output(command: &mut Command) -> Option<String> {
+    let output = command.output().ok()?;
+    if !output.status.success() { return None; }
+    String::from_utf8(output.stdout).ok()
+}
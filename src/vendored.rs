@@ -0,0 +1,280 @@
+use crate::{
+    component::{Component, Property},
+    native::{BuildScriptRun, license_near},
+};
+
+use semver::{Version};
+
+use std::{
+    collections::{HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+// what makes a directory a C/C++ source tree
+const SOURCE_EXTENSIONS: [&str; 6] = ["c", "h", "cc", "cpp", "hpp", "S"];
+// not vendored library code
+const SKIPPED_DIRS: [&str; 8] = ["target", ".git", "tests", "examples", "benches", "doc", "docs", "cmake"];
+
+// a C/C++ library's sources inside a -sys crate and where its version came from
+struct VendoredTree {
+    root: PathBuf,
+    name: String,
+    version: String,
+    version_source: PathBuf,
+}
+
+// -sys crates (links key) bundling C/C++ sources: one child component per library, with a purl
+pub fn vendored_libraries(run: &BuildScriptRun) -> Vec<Component> {
+    if run.links.is_none() { return vec![]; }
+
+    let mut trees: Vec<VendoredTree> = vec![];
+    find_trees(&run.package_root, 4, &mut trees);
+
+    // crates often bundle alternatives (zlib and zlib-ng), the one that was built has its headers in OUT_DIR
+    if trees.len() > 1 {
+        let out_headers = headers(&run.out_dir, 3);
+        let built: Vec<bool> = trees.iter().map(|tree| shares_header(tree, &out_headers)).collect();
+        match built.iter().any(|built| *built) {
+            true => {
+                let mut built = built.into_iter();
+                trees.retain(|_| built.next().unwrap_or_default());
+            }
+            false => println!("Cannot tell which of the libraries bundled in {} were built ({}), keeping all",
+                                run.package_name,
+                                trees.iter().map(|tree| tree.name.as_str()).collect::<Vec<&str>>().join(", ")),
+        }
+    }
+
+    let submodules = submodules(&run.package_root);
+    let crate_commit = vcs_commit(&run.package_root);
+
+    trees
+        .into_iter()
+        .map(|tree| {
+            let relative = |path: &Path| path.strip_prefix(&run.package_root).unwrap_or(path).to_path_buf();
+            let purl = match submodules.get(&relative(&tree.root)).and_then(|url| github_repository(url)) {
+                Some(repository) => format!("pkg:github/{}@{}", repository, tree.version),
+                None => format!("pkg:generic/{}@{}", tree.name, tree.version),
+            };
+            let (license_file, licenses) = match license_near(&tree.version_source, &run.package_root) {
+                Some((license_file, license)) => (Some(license_file), license),
+                None => (None, None),
+            };
+            let version = lenient_version(&tree.version);
+
+            let mut component = Component::create_vendored_component(
+                format!("vendored+{}@{}#{}@{}", run.package_name, run.package_version, tree.name, tree.version),
+                &tree.name,
+                version.clone(),
+                run.package_id.clone(),
+                purl,
+                licenses);
+            component.properties.push(Property::new("arielosbom:vendored_path", relative(&tree.root).display()));
+            component.properties.push(Property::new("arielosbom:version_source", relative(&tree.version_source).display()));
+            if version.to_string() != tree.version { component.properties.push(Property::new("arielosbom:version_string", &tree.version)); }
+            if let Some(license_file) = license_file { component.properties.push(Property::new("arielosbom:license_file", relative(&license_file).display())); }
+            // bundled sources (git submodules) are pinned by the crate's commit
+            if let Some(commit) = &crate_commit { component.properties.push(Property::new("arielosbom:crate_vcs_commit", commit)); }
+            component
+        })
+        .collect()
+}
+
+// directories with C/C++ sources and a recognizable version, not looking further into them
+fn find_trees(dir: &Path, depth: usize, trees: &mut Vec<VendoredTree>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    let mut dirs: Vec<PathBuf> = entries
+                                    .filter_map(|entry| entry.ok())
+                                    .map(|entry| entry.path())
+                                    .filter(|path| path.is_dir())
+                                    .filter(|path| path.file_name().is_some_and(|name| !SKIPPED_DIRS.iter().any(|skipped| name == *skipped)))
+                                    .collect();
+    dirs.sort();
+
+    for dir in dirs {
+        let has_sources = fs::read_dir(&dir)
+                            .map(|entries| entries
+                                            .filter_map(|entry| entry.ok())
+                                            .any(|entry| entry.path().extension().is_some_and(|extension| SOURCE_EXTENSIONS.iter().any(|source| extension == *source))))
+                            .unwrap_or_default();
+        match has_sources.then(|| detect_version(&dir)).flatten() {
+            Some(tree) => trees.push(tree),
+            None if depth > 0 => find_trees(&dir, depth - 1, trees),
+            None => {}
+        }
+    }
+}
+
+// version headers first (what the code reports about itself), then CMake, autoconf and Makefile metadata
+fn detect_version(dir: &Path) -> Option<VendoredTree> {
+    let dir_name = dir.file_name()?.to_string_lossy().into_owned();
+    let tree = |name: &str, version: String, version_source: PathBuf| VendoredTree { root: dir.to_path_buf(), name: name.into(), version, version_source };
+
+    let mut header_versions: Vec<(String, String, PathBuf)> = vec![];
+    for header in headers(dir, 2).into_iter().filter(|header| header.starts_with(dir.join("include")) || header.parent() == Some(dir)) {
+        let Ok(content) = fs::read_to_string(&header) else { continue };
+        header_versions.extend(defined_versions(&content).into_iter().map(|(prefix, version)| (prefix, version, header.clone())));
+    }
+    // ZLIBNG_VERSION in zlib-ng/, not the ZLIB_VERSION it also defines
+    let normalized_dir = normalize_name(&dir_name);
+    header_versions.sort_by_key(|(prefix, _, _)| match normalize_name(prefix) {
+        prefix if prefix == normalized_dir => 0,
+        prefix if !prefix.is_empty() && (normalized_dir.contains(&prefix) || prefix.contains(&normalized_dir)) => 1,
+        _ => 2,
+    });
+    if let Some((_, version, header)) = header_versions.into_iter().next() {
+        return Some(tree(&dir_name, version, header));
+    }
+
+    let cmake = dir.join("CMakeLists.txt");
+    if let Ok(content) = fs::read_to_string(&cmake)
+        && let Some((name, version)) = cmake_project(&content) {
+        return Some(tree(&name, version, cmake));
+    }
+
+    let configure = dir.join("configure.ac");
+    if let Ok(content) = fs::read_to_string(&configure)
+        && let Some((name, version)) = autoconf_init(&content) {
+        return Some(tree(&name, version, configure));
+    }
+
+    let makefile = dir.join("Makefile");
+    if let Ok(content) = fs::read_to_string(&makefile)
+        && let Some(version) = content.lines().find_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim_start_matches(':').trim();
+            (key.trim_end_matches([':', '?', ' ']) == "VERSION" && is_version(value)).then(|| value.to_string())
+        }) {
+        return Some(tree(&dir_name, version, makefile));
+    }
+
+    None
+}
+
+// #define FOO_VERSION "1.2.3" / FOO_VERSION_STRING "1.2.3", or FOO_VERSION_MAJOR/_MINOR/_PATCH -> (FOO, version)
+fn defined_versions(content: &str) -> Vec<(String, String)> {
+    let mut versions: Vec<(String, String)> = vec![];
+    let mut numbers: HashMap<(String, &str), String> = HashMap::new();
+
+    for line in content.lines() {
+        let mut words = line.split_whitespace();
+        if words.next() != Some("#define") { continue; }
+        let (Some(macro_name), Some(value)) = (words.next(), words.next()) else { continue };
+
+        let quoted = value.trim_matches('"');
+        if value.starts_with('"') && is_version(quoted)
+            && let Some(prefix) = macro_name.strip_suffix("_VERSION").or_else(|| macro_name.strip_suffix("_VERSION_STRING")) {
+            versions.push((prefix.into(), quoted.into()));
+            continue;
+        }
+        if !value.chars().all(|character| character.is_ascii_digit()) { continue; }
+        for part in ["MAJOR", "MINOR", "PATCH"] {
+            let prefix = macro_name.strip_suffix(&format!("_VERSION_{}", part)).or_else(|| macro_name.strip_suffix(&format!("_{}_VERSION", part)));
+            if let Some(prefix) = prefix { numbers.insert((prefix.into(), part), value.into()); }
+        }
+    }
+
+    let mut prefixes: Vec<&String> = numbers.keys().filter(|(_, part)| *part == "MAJOR").map(|(prefix, _)| prefix).collect();
+    prefixes.sort();
+    for prefix in prefixes {
+        let Some(minor) = numbers.get(&(prefix.clone(), "MINOR")) else { continue };
+        let patch = numbers.get(&(prefix.clone(), "PATCH")).map(|patch| patch.as_str()).unwrap_or("0");
+        versions.push((prefix.clone(), format!("{}.{}.{}", numbers[&(prefix.clone(), "MAJOR")], minor, patch)));
+    }
+    versions
+}
+
+// project(<name> VERSION <version> ...), only with a literal version
+fn cmake_project(content: &str) -> Option<(String, String)> {
+    let start = content.to_lowercase().find("project(")?;
+    let arguments = content[start + "project(".len()..].split(')').next()?;
+    let mut words = arguments.split_whitespace();
+    let name = words.next()?;
+    let version = words.skip_while(|word| *word != "VERSION").nth(1)?;
+    is_version(version).then(|| (name.into(), version.into()))
+}
+
+// AC_INIT([name], [version], ...)
+fn autoconf_init(content: &str) -> Option<(String, String)> {
+    let start = content.find("AC_INIT(")?;
+    let arguments = content[start + "AC_INIT(".len()..].split(')').next()?;
+    let mut fields = arguments.split(',').map(|field| field.trim().trim_start_matches('[').trim_end_matches(']').trim());
+    let (name, version) = (fields.next()?, fields.next()?);
+    is_version(version).then(|| (name.into(), version.into()))
+}
+
+fn is_version(value: &str) -> bool {
+    value.starts_with(|character: char| character.is_ascii_digit()) && value.contains('.')
+}
+
+// "zlib-ng" / "ZLIBNG" -> "zlibng"
+fn normalize_name(name: &str) -> String {
+    name.chars().filter(|character| character.is_alphanumeric()).collect::<String>().to_lowercase()
+}
+
+// "1.3.1.zlib-ng" -> 1.3.1, "2.28" -> 2.28.0; C libraries rarely use semver
+fn lenient_version(version: &str) -> Version {
+    if let Ok(version) = Version::parse(version) { return version; }
+    let mut numbers = version
+                        .split(['.', '-', '_', '+'])
+                        .map_while(|part| part.parse::<u64>().ok())
+                        .chain(std::iter::repeat(0));
+    Version::new(numbers.next().unwrap_or_default(), numbers.next().unwrap_or_default(), numbers.next().unwrap_or_default())
+}
+
+// .h and .h.in files up to depth levels down
+fn headers(dir: &Path, depth: usize) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else { return vec![] };
+    let mut headers: Vec<PathBuf> = vec![];
+    for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+        if path.is_dir() {
+            if depth > 0 { headers.extend(self::headers(&path, depth - 1)); }
+        } else if path.file_name().is_some_and(|name| name.to_string_lossy().ends_with(".h") || name.to_string_lossy().ends_with(".h.in")) {
+            headers.push(path);
+        }
+    }
+    headers.sort();
+    headers
+}
+
+// build scripts copy the public headers of what they built into OUT_DIR
+fn shares_header(tree: &VendoredTree, out_headers: &[PathBuf]) -> bool {
+    headers(&tree.root, 2).iter().any(|header| out_headers
+                                                .iter()
+                                                .filter(|out_header| out_header.file_name() == header.file_name())
+                                                .any(|out_header| fs::read(out_header).ok().is_some_and(|content| fs::read(header).ok() == Some(content))))
+}
+
+// .gitmodules: submodule path (relative to the crate) -> url
+fn submodules(package_root: &Path) -> HashMap<PathBuf, String> {
+    let Ok(content) = fs::read_to_string(package_root.join(".gitmodules")) else { return HashMap::new() };
+    let mut submodules: HashMap<PathBuf, String> = HashMap::new();
+    let mut path: Option<PathBuf> = None;
+    for line in content.lines().map(|line| line.trim()) {
+        let Some((key, value)) = line.split_once('=') else {
+            path = None;
+            continue;
+        };
+        match key.trim() {
+            "path" => path = Some(PathBuf::from(value.trim())),
+            "url" => if let Some(path) = &path { submodules.insert(path.clone(), value.trim().to_string()); },
+            _ => {}
+        }
+    }
+    submodules
+}
+
+// "https://github.com/madler/zlib.git" / "git@github.com:madler/zlib" -> "madler/zlib"
+fn github_repository(url: &str) -> Option<String> {
+    let (_, path) = url.split_once("github.com")?;
+    let path = path.trim_start_matches([':', '/']).trim_end_matches('/').trim_end_matches(".git");
+    (path.split('/').count() == 2).then(|| path.to_string())
+}
+
+// commit the crate was published from (cargo package writes it to .cargo_vcs_info.json)
+fn vcs_commit(package_root: &Path) -> Option<String> {
+    let content = fs::read_to_string(package_root.join(".cargo_vcs_info.json")).ok()?;
+    let vcs_info: serde_json::Value = serde_json::from_str(&content).ok()?;
+    vcs_info.get("git")?.get("sha1")?.as_str().map(|sha1| sha1.to_string())
+}
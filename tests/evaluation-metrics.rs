@@ -1,4 +1,13 @@
 mod evaluation_metrics;
+
+// shared with the actual tool instead of a copy of the build file handling
+#[allow(dead_code)]
+#[path = "../src/ninja.rs"]
+mod ninja;
+#[allow(dead_code)]
+#[path = "../src/invocation.rs"]
+mod invocation;
+
 use evaluation_metrics::comparison::{generate_comparisons};
 
 use std::fs::{File};
@@ -7,9 +16,9 @@ use std::io::{BufRead, BufReader};
 // TODO/IDEAS:
     // multiple test projects ~> diff. section in ini file -> diff. folder in results for each
 
-static TEST_PATH: &'static str = "./tests/evaluation_metrics/";
-static TOOL_PATH: &'static str = "./tests/evaluation_metrics/tools/";
-static RESULT_PATH: &'static str = "./tests/evaluation_metrics/results/";
+static TEST_PATH: &str = "./tests/evaluation_metrics/";
+static TOOL_PATH: &str = "./tests/evaluation_metrics/tools/";
+static RESULT_PATH: &str = "./tests/evaluation_metrics/results/";
 
 #[test]
 fn eval_metrics() {
@@ -22,7 +31,7 @@ fn eval_metrics() {
     let mut config_lines: Vec<String> = vec![];
     for line in buffer.lines() {
         match line {
-            Ok(content) => {config_lines.push(content.split('=').last().unwrap().into());},
+            Ok(content) => {config_lines.push(content.split('=').next_back().unwrap().into());},
             Err(_) => panic!("Failed to parse ini file")
         };
     }
@@ -5,9 +5,9 @@ use std::{
     collections::BTreeSet, 
     env::{current_dir},
     fmt::Formatter, 
-    fs::File, 
-    io::{BufRead, BufReader, BufWriter, Write}, 
-    path::PathBuf, 
+    fs::{self, File}, 
+    io::{BufWriter, Write}, 
+    path::{Path, PathBuf}, 
     process::Command, 
 };
 
@@ -15,7 +15,11 @@ use std::{
     // enum for diff tools with version field e.g.
     // multiple test projects in the future ~> change path stuff
 
-use crate::{RESULT_PATH, TOOL_PATH};
+use crate::{
+    RESULT_PATH, TOOL_PATH,
+    invocation::{CargoInvocation},
+    ninja::{NinjaFile},
+};
 
 #[derive(Deserialize, Eq, PartialEq, PartialOrd, Ord)]
 pub struct Component {
@@ -32,6 +36,7 @@ impl std::fmt::Display for Component {
     }
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Deserialize, Eq, PartialEq)]
 struct SBOM {
     components: Vec<Component>
@@ -68,13 +73,13 @@ pub fn generate_comparisons(project_path: &str, syft_version: &str, crc_version:
     let bloat_set = generate_bloat_set("cargo_bloat.json".into()).iter().map(|function| function.split('?').next().unwrap().into()).collect();
     let ariel_bloat_comp_set = ariel_set.iter().map(|function| function.replace("-", "_").split('@').next().unwrap().into()).collect::<BTreeSet<String>>();
 
-    let ariel_syft_intersection: Vec<String> = ariel_set.intersection(&syft_set).map(|component| component.clone()).collect();
+    let ariel_syft_intersection: Vec<String> = ariel_set.intersection(&syft_set).cloned().collect();
     let ariel_crc_intersection: Vec<String> = ariel_set.intersection(&crc_set).map(|component| component.to_string()).collect();
     let ariel_syft_diff: Vec<String> = ariel_set.difference(&syft_set).map(|component| component.to_string()).collect();
     let ariel_crc_diff: Vec<String> = ariel_set.difference(&crc_set).map(|component| component.to_string()).collect();
     let syft_ariel_diff: Vec<String> = syft_set.difference(&ariel_set).map(|component| component.to_string()).collect();
     let crc_ariel_diff: Vec<String> = crc_set.difference(&ariel_set).map(|component| component.to_string()).collect();
-    let ariel_bloat_intersection: Vec<String> = ariel_bloat_comp_set.intersection(&bloat_set).map(|component| component.clone()).collect();
+    let ariel_bloat_intersection: Vec<String> = ariel_bloat_comp_set.intersection(&bloat_set).cloned().collect();
     let ariel_bloat_diff: Vec<String> = ariel_bloat_comp_set.difference(&bloat_set).map(|component| component.to_string()).collect();
     let bloat_ariel_diff: Vec<String> = bloat_set.difference(&ariel_bloat_comp_set).map(|component| component.to_string()).collect();
 
@@ -102,19 +107,19 @@ fn generate_comparison_file(
 
     let mut writer = BufWriter::new(file);
 
-    writer.write(format!("Components recognized by both tools: {}\n", both).as_bytes()).expect("failed writing to bufwriter");
+    writer.write_all(format!("Components recognized by both tools: {}\n", both).as_bytes()).expect("failed writing to bufwriter");
     for component in intersection {
-        writer.write(format!("{}\n", component.to_string()).as_bytes()).expect("failed writing to bufwriter");
+        writer.write_all(format!("{}\n", component).as_bytes()).expect("failed writing to bufwriter");
     }
 
-    writer.write(format!("\nComponents only recognized by ArielOSBOM: {}\n", ariel).as_bytes()).expect("failed writing to bufwriter");
+    writer.write_all(format!("\nComponents only recognized by ArielOSBOM: {}\n", ariel).as_bytes()).expect("failed writing to bufwriter");
     for component in ariel_diff {
-        writer.write(format!("{}\n", component.to_string()).as_bytes()).expect("failed writing to bufwriter");
+        writer.write_all(format!("{}\n", component).as_bytes()).expect("failed writing to bufwriter");
     }
     
-    writer.write(format!("\nComponents only recognized by {}: {}\n", compared, tool).as_bytes()).expect("failed writing to bufwrite");
+    writer.write_all(format!("\nComponents only recognized by {}: {}\n", compared, tool).as_bytes()).expect("failed writing to bufwrite");
     for component in tool_diff {
-        writer.write(format!("{}\n", component.to_string()).as_bytes()).expect("failed writing to bufwriter");
+        writer.write_all(format!("{}\n", component).as_bytes()).expect("failed writing to bufwriter");
     }
 }
 
@@ -131,19 +136,19 @@ fn generate_bloat_comparison(intersection: Vec<String>, ariel_diff: Vec<String>,
 
     let mut writer = BufWriter::new(file);
 
-    writer.write(format!("Components recognized by both tools: {}\n", both).as_bytes()).expect("failed writing to bufwriter");
+    writer.write_all(format!("Components recognized by both tools: {}\n", both).as_bytes()).expect("failed writing to bufwriter");
     for component in intersection {
-        writer.write(format!("{}\n", component.to_string()).as_bytes()).expect("failed writing to bufwriter");
+        writer.write_all(format!("{}\n", component).as_bytes()).expect("failed writing to bufwriter");
     }
 
-    writer.write(format!("\nComponents only recognized by ArielOSBOM: {}\n", ariel).as_bytes()).expect("failed writing to bufwriter");
+    writer.write_all(format!("\nComponents only recognized by ArielOSBOM: {}\n", ariel).as_bytes()).expect("failed writing to bufwriter");
     for component in ariel_diff {
-        writer.write(format!("{}\n", component.to_string()).as_bytes()).expect("failed writing to bufwriter");
+        writer.write_all(format!("{}\n", component).as_bytes()).expect("failed writing to bufwriter");
     }
     
-    writer.write(format!("\nComponents only recognized by bloat: {}\n", tool).as_bytes()).expect("failed writing to bufwrite");
+    writer.write_all(format!("\nComponents only recognized by bloat: {}\n", tool).as_bytes()).expect("failed writing to bufwrite");
     for component in bloat_diff {
-        writer.write(format!("{}\n", component.to_string()).as_bytes()).expect("failed writing to bufwriter");
+        writer.write_all(format!("{}\n", component).as_bytes()).expect("failed writing to bufwriter");
     }
 }
 
@@ -168,7 +173,7 @@ fn generate_bloat_set(file_name: PathBuf) -> BTreeSet<String> {
                                         None => &function.function_name
                                     }.clone())
                                     .collect();
-    return set
+    set
 }
 
 fn generate_component_set(file_name: PathBuf) -> BTreeSet<String> {
@@ -187,43 +192,28 @@ fn generate_component_set(file_name: PathBuf) -> BTreeSet<String> {
 
     let set: BTreeSet<String> = file_content.components.iter().map(|component| component.to_string()).collect();
     
-    return set
+    set
 }
 
 fn generate_cargo_bloat(project_path: &str) {
 
-    let file = match File::open(format!("{}build/build-local.ninja", project_path)) {
-        Ok(file) => file,
-        Err(e) => panic!("Could not open build-local.ninja: {}", e)        
+    let ninja_file = match NinjaFile::load(Path::new("build/build-local.ninja"), Path::new(project_path)) {
+        Ok(ninja_file) => ninja_file,
+        Err(e) => panic!("Could not parse build-local.ninja: {}", e)
     };
 
-    let reader =BufReader::new(file);
-    let mut lines: Vec<String> = vec![];
-    for line in reader.lines() {
-        match line {
-            Ok(content) => lines.push(content),
-            Err(_) => panic!("Failed to read build-local.ninja")
-        };
-    }
-
-    let command_split: Vec<&str> = lines[3].split(" cargo ").collect();
+    let invocation = match CargoInvocation::find_in_ninja(&ninja_file).into_iter().next() {
+        Some(invocation) => invocation,
+        None => panic!("No cargo build found in build-local.ninja")
+    };
 
     let current_dir = current_dir().unwrap();
 
-    let (command_left, command_middle, command_right): (&str, &str, &str) = (
-        &command_split[0][12..],
-        "cargo bloat --full-fn -n 100000 --message-format json",
-        &command_split[1].split(" &&").collect::<Vec<&str>>()[0]
-    );
-    
-    //println!("{} {}{} > {}/{}cargo_bloat.json", command_left, command_middle, command_right, current_dir.display(), RESULT_PATH);
-    //panic!();
-    Command::new("sh")
-        .current_dir(project_path)
-        .arg("-c")
-        .arg(format!("{} {}{} > {}/{}cargo_bloat.json", command_left, command_middle, command_right, current_dir.display(), RESULT_PATH))
+    let output = invocation
+        .command(Path::new(project_path), "bloat", &["--full-fn", "-n", "100000", "--message-format", "json"])
         .output()
         .expect("Something failed with cargo_bloat");
+    fs::write(format!("{}/{}cargo_bloat.json", current_dir.display(), RESULT_PATH), output.stdout).expect("Could not write cargo_bloat.json");
     println!("bloat created");
 
 }
@@ -236,7 +226,7 @@ fn generate_comparison_sboms(
 
     // Syft
     Command::new(format!("{}syft_{}/syft", TOOL_PATH, syft_version))
-        .arg(format!("{}", project_path))
+        .arg(project_path)
         .arg("-o")
         .arg(format!("cyclonedx-json=syft_{}.json", syft_version))
         .arg("--exclude")
@@ -268,7 +258,7 @@ fn generate_comparison_sboms(
         .arg("run")
         .arg("--")
         .arg("-r")
-        .arg(format!("{}", project_path))
+        .arg(project_path)
         .arg("-o")
         .arg("arielosbom_eval")
         .output()
@@ -278,17 +268,17 @@ fn generate_comparison_sboms(
     // thread::sleep(Duration::from_secs(5));
     Command::new("mv")
         .arg(format!("./syft_{}.json", syft_version))
-        .arg(format!("{}", RESULT_PATH))
+        .arg(RESULT_PATH)
         .output()
         .expect("Failed to move syft output to results directory");
     Command::new("mv")
         .arg(format!("{}crc_{}.json", project_path, crc_version))
-        .arg(format!("{}", RESULT_PATH))
+        .arg(RESULT_PATH)
         .output()
         .expect("Failed to move crc output to results directory");
     Command::new("mv")
         .arg("./arielosbom_eval.json")
-        .arg(format!("{}", RESULT_PATH))
+        .arg(RESULT_PATH)
         .output()
         .expect("Failed to move crc output to results directory");
     //thread::sleep(Duration::from_secs(1));